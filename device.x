@@ -0,0 +1,20 @@
+PROVIDE(PM = DefaultHandler);
+PROVIDE(SYSCTRL = DefaultHandler);
+PROVIDE(WDT = DefaultHandler);
+PROVIDE(RTC = DefaultHandler);
+PROVIDE(EIC = DefaultHandler);
+PROVIDE(NVMCTRL = DefaultHandler);
+PROVIDE(EVSYS = DefaultHandler);
+PROVIDE(SERCOM0 = DefaultHandler);
+PROVIDE(SERCOM1 = DefaultHandler);
+PROVIDE(SERCOM2 = DefaultHandler);
+PROVIDE(SERCOM3 = DefaultHandler);
+PROVIDE(TC0 = DefaultHandler);
+PROVIDE(TC1 = DefaultHandler);
+PROVIDE(TC2 = DefaultHandler);
+PROVIDE(TC3 = DefaultHandler);
+PROVIDE(TC4 = DefaultHandler);
+PROVIDE(TC5 = DefaultHandler);
+PROVIDE(ADC = DefaultHandler);
+PROVIDE(AC = DefaultHandler);
+PROVIDE(DAC = DefaultHandler);
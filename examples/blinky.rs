@@ -1,35 +1,37 @@
-#![feature(used)]
+#![no_main]
 #![no_std]
 
 extern crate panic_abort;
 
 extern crate atsamd20e15a;
 extern crate cortex_m;
+extern crate embedded_hal;
 
-use atsamd20e15a::{PORT, SYSCTRL};
-use cortex_m::interrupt;
+use atsamd20e15a::entry;
+use atsamd20e15a::gpio::GpioExt;
+use embedded_hal::digital::v2::OutputPin;
 
-fn main() {
-    interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        let sysctrl = SYSCTRL.borrow(cs);
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+    let sysctrl = &p.SYSCTRL;
+    let mut port = p.PORT.split();
 
-        /* Use unscaled system oscillator (i.e. full 8MHz) */
-        sysctrl.osc8m.write(|w| unsafe { w.presc().bits(0) });
+    /* Use unscaled system oscillator (i.e. full 8MHz) */
+    sysctrl.osc8m.write(|w| unsafe { w.presc().bits(0) });
 
-        /* Initialise PA0 */
-        port.outset.modify(|_, w| unsafe { w.outset().bits(1) });
-        port.dir.modify(|_, w| unsafe { w.dir().bits(1) });
+    /* Initialise PA0 */
+    let mut pa0 = port.pa0.into_push_pull_output(&mut port.port);
+    pa0.set_high().unwrap();
 
-        loop {
-            /* Turn PA0 on a million times in a row */
-            for _ in 0..1_000_000 {
-                port.outclr.modify(|_, w| unsafe { w.outclr().bits(1) });
-            }
-            /* Then turn PA0 off a million times in a row */
-            for _ in 0..1_000_000 {
-                port.outset.modify(|_, w| unsafe { w.outset().bits(1) });
-            }
+    loop {
+        /* Turn PA0 on a million times in a row */
+        for _ in 0..1_000_000 {
+            pa0.set_low().unwrap();
         }
-    });
+        /* Then turn PA0 off a million times in a row */
+        for _ in 0..1_000_000 {
+            pa0.set_high().unwrap();
+        }
+    }
 }
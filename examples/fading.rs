@@ -1,59 +1,54 @@
-#![feature(used)]
+#![no_main]
 #![no_std]
 
 extern crate panic_abort;
 
-#[macro_use(exception, interrupt)]
 extern crate atsamd20e15a;
 extern crate cortex_m;
 
-use atsamd20e15a::{init_48_mhz_clock, setup_tc0, PORT, SYST};
-use cortex_m::interrupt;
-use cortex_m::peripheral::SystClkSource;
+use atsamd20e15a::clock::{ClockConfig, ClockSource, Dfll48m, Generator, Osc8mPrescaler};
+use atsamd20e15a::delay::systick_periodic;
+use atsamd20e15a::time::U32Ext;
+use atsamd20e15a::{entry, exception, interrupt, pull_pins_high, pull_pins_low, setup_tc0};
 
 use atsamd20e15a::snowflake;
 
-fn main() {
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+
     for _ in 0..200_000 {
         cortex_m::asm::nop();
     }
 
-    /* Initialise clock, has its own critical section */
-    init_48_mhz_clock();
-
-    /* Enter critical section */
-    interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        let syst = SYST.borrow(cs);
-
-        /* Initialise PA0-PA24 to high */
-        port.outset
-            .write(|w| unsafe { w.outset().bits(0x1FF_FFFF) });
+    /* Initialise clock, CPU runs from the DFLL48M in open-loop mode */
+    ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .dfll48m(Dfll48m::OpenLoop)
+        .generator(Generator::Gclk0, ClockSource::Dfll48m, 1)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
 
-        /* Set PA0-PA24 as output */
-        port.dir.write(|w| unsafe { w.dir().bits(0x1FF_FFFF) });
+    let port = &p.PORT;
 
-        /* Set PA25 to input with pull-up and external interrupt enabled */
-        port.pincfg[25].modify(|_, w| w.inen().set_bit().pullen().set_bit().pmuxen().set_bit());
+    /* Initialise PA0-PA24 to high */
+    port.outset
+        .write(|w| unsafe { w.outset().bits(0x1FF_FFFF) });
 
-        /* Initialise SysTick counter with a defined value */
-        unsafe { syst.cvr.write(1) };
+    /* Set PA0-PA24 as output */
+    port.dir.write(|w| unsafe { w.dir().bits(0x1FF_FFFF) });
 
-        /* Set source for SysTick counter, here full operating frequency (== 8MHz) */
-        syst.set_clock_source(SystClkSource::Core);
+    /* Select function A (EIC/EXTINT13) for PA25, it's the odd pin of PMUX12 */
+    port.pmux0_[12].modify(|_, w| w.pmuxo().a());
 
-        /* Set reload value, i.e. timer delay (== 1/24s) */
-        syst.set_reload(2_000_000);
+    /* Set PA25 to input with pull-up and external interrupt enabled */
+    port.pincfg[25].modify(|_, w| w.inen().set_bit().pullen().set_bit().pmuxen().set_bit());
 
-        /* Start counter */
-        syst.enable_counter();
-
-        /* Start interrupt generation */
-        syst.enable_interrupt();
-    });
+    /* Fire the SysTick exception 24 times per second */
+    systick_periodic(24.hz());
 
     /* Setup timer interrupt with 240kHz frequency */
-    setup_tc0(200);
+    setup_tc0(&p.GCLK, &p.PM, &p.TC0, 200);
 
     /* Initialise an LED gradient */
     let leds = snowflake::proto_leds();
@@ -71,13 +66,17 @@ fn main() {
     leds[11].set(30);
     leds[12].set(10);
     leds[13].set(1);
+
+    loop {
+        /* Sleep until the next interrupt */
+        cortex_m::asm::wfi();
+    }
 }
 
 /* Define an exception handler, i.e. function to call when the specific exception occurs. Here our SysTick timer
- * trips the running function */
-exception!(SYS_TICK, running);
-
-fn running() {
+ * trips this handler */
+#[exception]
+fn SysTick() {
     let leds = &mut snowflake::proto_leds();
 
     /* Rotate LED values */
@@ -87,40 +86,33 @@ fn running() {
     snowflake::pwmcache().calculate_perceived(leds);
 }
 
-/* Define an interrupt handler, i.e. function to call when the specific interrupt occurs. Here our
- * timer to handle the PWM trips the fade function */
-interrupt!(TC0, fade_handler, locals: {
-    time: u8 = 0;
-});
-
 /* Place function into RAM to avoid flash wait states */
 #[link_section = ".data"]
 #[inline(never)]
 /* Apply the current LED intensity of all LEDs */
 fn fade(time: u8) -> u8 {
-    /* Enter critical section */
-    cortex_m::interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        let tc0 = atsamd20e15a::TC0.borrow(cs);
-        tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
+    /* Acknowledge the interrupt, INTFLAG is write-one-to-clear so we don't need to own TC0 */
+    let tc0 = unsafe { &*atsamd20e15a::TC0::ptr() }.count16();
+    tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
 
-        /* Retrieve PWM values for current time */
-        let newstate = snowflake::pwmcache()[time];
+    /* Retrieve PWM values for current time */
+    let newstate = snowflake::pwmcache()[time];
 
-        /* Enable LEDs */
-        port.outclr
-            .modify(|_, w| unsafe { w.outclr().bits(newstate) });
+    /* Enable LEDs */
+    pull_pins_low(newstate);
 
-        /* Disable LEDs */
-        port.outset
-            .modify(|_, w| unsafe { w.outset().bits(!newstate) });
-    });
+    /* Disable LEDs */
+    pull_pins_high(!newstate);
 
     time - 1
 }
 
-/* The interrupt handler to call our main fade function residing in RAM */
-fn fade_handler(l: &mut TC0::Locals) {
+/* Define an interrupt handler, i.e. function to call when the specific interrupt occurs. Here our
+ * timer to handle the PWM trips this handler which calls our main fade function residing in RAM */
+#[interrupt]
+fn TC0() {
+    static mut TIME: u8 = 0;
+
     /* Call into handler placed in RAM to avoid flash wait states */
-    l.time = fade(l.time);
+    *TIME = fade(*TIME);
 }
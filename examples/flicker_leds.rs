@@ -1,78 +1,65 @@
-#![feature(used)]
+#![no_main]
 #![no_std]
 
 extern crate panic_abort;
 
-#[macro_use(exception)]
 extern crate atsamd20e15a;
 extern crate cortex_m;
 
-use atsamd20e15a::{PORT, SYSCTRL, SYST};
-use cortex_m::interrupt;
-use cortex_m::peripheral::SystClkSource;
+use atsamd20e15a::clock::{ClockConfig, Osc8mPrescaler};
+use atsamd20e15a::delay::systick_periodic;
+use atsamd20e15a::time::U32Ext;
+use atsamd20e15a::{entry, exception, pull_pins_high, pull_pins_low};
 
-fn main() {
-    interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        let sysctrl = SYSCTRL.borrow(cs);
-        let syst = SYST.borrow(cs);
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
 
-        /* Use unscaled system oscillator (i.e. full 8MHz) */
-        sysctrl.osc8m.write(|w| unsafe { w.presc().bits(0) });
+    let port = &p.PORT;
 
-        /* Initialise PA0-P04 */
-        port.outset.modify(|_, w| unsafe { w.outset().bits(31) });
-        port.dir.modify(|_, w| unsafe { w.dir().bits(31) });
+    /* Use unscaled system oscillator (i.e. full 8MHz) */
+    ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
 
-        /* Initialise SysTick counter with a defined value */
-        unsafe { syst.cvr.write(1) };
+    /* Initialise PA0-P04 */
+    port.outset.modify(|_, w| unsafe { w.outset().bits(31) });
+    port.dir.modify(|_, w| unsafe { w.dir().bits(31) });
 
-        /* Set source for SysTick counter, here full operating frequency (== 8MHz) */
-        syst.set_clock_source(SystClkSource::Core);
+    /* Fire the SysTick exception 16 times per second */
+    systick_periodic(16.hz());
 
-        /* Set reload value, i.e. timer delay (== 64ms) */
-        syst.set_reload(500_000);
-
-        /* Start counter */
-        syst.enable_counter();
-
-        /* Start interrupt generation */
-        syst.enable_interrupt();
-    });
+    loop {
+        /* Sleep until the next interrupt */
+        cortex_m::asm::wfi();
+    }
 }
 
 /* Define an exception, i.e. function to call when exception occurs. Here if our SysTick timer
- * trips the flicker function */
-exception!(SYS_TICK, flicker, locals: {
-    state: bool = false;
-    rand: u32 = 2;
-});
-
-fn flicker(l: &mut SYS_TICK::Locals) {
-    /* Enter critical section */
-    cortex_m::interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-
-        /* If next state is true */
-        if l.state {
-            /* Enable LEDs */
-            port.outclr
-                .modify(|_, w| unsafe { w.outclr().bits(l.rand) });
-
-            /* And set next state to false */
-            l.state = false;
-        } else {
-            /* Disable LEDs */
-            port.outset
-                .modify(|_, w| unsafe { w.outset().bits(l.rand) });
-
-            /* And set next state to false */
-            l.state = true;
-
-            /* Use PRBS31 to generate next LED sequence */
-            let a = l.rand;
-            let newbit = ((a >> 31) ^ (a >> 28)) & 1;
-            l.rand = (a << 1) | newbit;
-        }
-    });
+ * trips this handler */
+#[exception]
+fn SysTick() {
+    static mut STATE: bool = false;
+    static mut RAND: u32 = 2;
+
+    /* If next state is true */
+    if *STATE {
+        /* Enable LEDs */
+        pull_pins_low(*RAND);
+
+        /* And set next state to false */
+        *STATE = false;
+    } else {
+        /* Disable LEDs */
+        pull_pins_high(*RAND);
+
+        /* And set next state to false */
+        *STATE = true;
+
+        /* Use PRBS31 to generate next LED sequence */
+        let a = *RAND;
+        let newbit = ((a >> 31) ^ (a >> 28)) & 1;
+        *RAND = (a << 1) | newbit;
+    }
 }
@@ -0,0 +1,76 @@
+#![no_main]
+#![no_std]
+
+extern crate panic_abort;
+
+extern crate atsamd20e15a;
+extern crate cortex_m;
+extern crate embedded_hal;
+extern crate nb;
+
+use atsamd20e15a::capture::{Capture, Mode};
+use atsamd20e15a::clock::{ClockConfig, ClockId, ClockSource, Dfll48m, Generator, Osc8mPrescaler};
+use atsamd20e15a::eic::Eic;
+use atsamd20e15a::entry;
+use atsamd20e15a::evsys::{Channel, EventSystem};
+use atsamd20e15a::gpio::GpioExt;
+use atsamd20e15a::time::U32Ext;
+use embedded_hal::digital::v2::OutputPin;
+
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+
+    /* Initialise clock, CPU, EIC and TC4 run from the DFLL48M in open-loop mode */
+    let clocks = ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .dfll48m(Dfll48m::OpenLoop)
+        .generator(Generator::Gclk0, ClockSource::Dfll48m, 1)
+        .route(ClockId::Eic, Generator::Gclk0)
+        .route(ClockId::Tc4Tc5, Generator::Gclk0)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
+
+    let mut port = p.PORT.split();
+
+    /* PA0 shows whether the signal on PA25 is high for more than half of its period */
+    let mut pa0 = port.pa0.into_push_pull_output(&mut port.port);
+
+    /* Hand PA25 with its pull-up to the EIC */
+    let pa25 = port
+        .pa25
+        .into_pull_up_input(&mut port.port)
+        .into_function_a(&mut port.port);
+
+    let mut eic = Eic::new(p.EIC, &p.PM, &clocks).unwrap();
+    let mut evsys = EventSystem::new(p.EVSYS, &p.PM);
+
+    /* Capture period and pulse width of PA25 with TC4 at 48MHz / 64 = 750kHz via EVSYS channel 0 */
+    let mut capture = Capture::tc4(
+        p.TC4,
+        &p.PM,
+        &clocks,
+        &mut eic,
+        &mut evsys,
+        &pa25,
+        Channel::Ch0,
+        Mode::PeriodPulseWidth,
+        750.khz(),
+    )
+    .unwrap();
+
+    loop {
+        match capture.read() {
+            Ok(measurement) => {
+                if measurement.pulse_width as u32 * 2 > measurement.period as u32 {
+                    pa0.set_high().unwrap();
+                } else {
+                    pa0.set_low().unwrap();
+                }
+            }
+            Err(nb::Error::WouldBlock) => {}
+            /* We were too slow for a capture, just take the next one */
+            Err(nb::Error::Other(_)) => {}
+        }
+    }
+}
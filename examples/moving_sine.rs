@@ -1,36 +1,46 @@
-#![feature(used)]
+#![no_main]
 #![no_std]
 
 extern crate panic_abort;
 
-#[macro_use(exception, interrupt)]
 extern crate atsamd20e15a;
 extern crate cortex_m;
 
+use atsamd20e15a::clock::{ClockConfig, ClockSource, Dfll48m, Generator, Osc8mPrescaler};
+use atsamd20e15a::delay::systick_periodic;
+use atsamd20e15a::time::U32Ext;
 use atsamd20e15a::{
-    delay_init, init_48_mhz_clock, init_gpios, init_systick, pull_pins_high, pull_pins_low,
-    setup_tc0, snowflake,
+    delay_init, entry, exception, init_gpios, interrupt, pull_pins_high, pull_pins_low, setup_tc0,
+    snowflake,
 };
 
 /* If set to true, enables a high edge on data out pin during PWM value calculation for measurement
  * via oscilloscope */
 const DEBUG: bool = false;
 
-fn main() {
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+
     /* ATSAMD is bitchy, let's delay a bit so we can attach with a debugger if we need to */
     delay_init();
 
-    /* Initialise clock, has its own critical section */
-    init_48_mhz_clock();
+    /* Initialise clock, CPU runs from the DFLL48M in open-loop mode */
+    ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .dfll48m(Dfll48m::OpenLoop)
+        .generator(Generator::Gclk0, ClockSource::Dfll48m, 1)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
 
     /* Initialise the used GPIOs */
-    init_gpios();
+    init_gpios(&p.PORT);
 
-    /* Initialise the SysTick timer and exception */
-    init_systick(3_000_000);
+    /* Fire the SysTick exception 16 times per second */
+    systick_periodic(16.hz());
 
     /* Setup timer interrupt with 480kHz frequency */
-    setup_tc0(100);
+    setup_tc0(&p.GCLK, &p.PM, &p.TC0, 100);
 
     /* Initialise an LED gradient using the values of a sine wave */
     let leds = snowflake::snowflake_leds();
@@ -43,13 +53,17 @@ fn main() {
 
     /* Recalculate PWM values */
     snowflake::pwmcache().calculate_perceived(leds);
+
+    loop {
+        /* Sleep until the next interrupt */
+        cortex_m::asm::wfi();
+    }
 }
 
 /* Define an exception handler, i.e. function to call when the specific exception occurs. Here our SysTick timer
- * trips the running function */
-exception!(SYS_TICK, running);
-
-fn running() {
+ * trips this handler */
+#[exception]
+fn SysTick() {
     let leds = &mut snowflake::snowflake_leds();
 
     /* Rotate LED values */
@@ -68,15 +82,13 @@ fn running() {
 }
 
 /* Define an interrupt handler, i.e. function to call when the specific interrupt occurs. Here our
- * timer to handle the PWM trips the fade function */
-interrupt!(TC0, pwm_handler, locals: {
-    time: u8 = 0;
-});
+ * timer to handle the PWM trips this handler which calls our main fade function residing in RAM */
+#[interrupt]
+fn TC0() {
+    static mut TIME: u8 = 0;
 
-/* The interrupt handler to call our main fade function residing in RAM */
-fn pwm_handler(l: &mut TC0::Locals) {
     /* Call into handler placed in RAM to avoid flash wait states */
-    l.time = do_pwm(l.time);
+    *TIME = do_pwm(*TIME);
 }
 
 /* Place function into RAM to avoid flash wait states */
@@ -84,11 +96,9 @@ fn pwm_handler(l: &mut TC0::Locals) {
 #[inline(never)]
 /* Apply the current LED intensity of all LEDs */
 fn do_pwm(time: u8) -> u8 {
-    /* Enter critical section */
-    cortex_m::interrupt::free(|cs| {
-        let tc0 = atsamd20e15a::TC0.borrow(cs);
-        tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
-    });
+    /* Acknowledge the interrupt, INTFLAG is write-one-to-clear so we don't need to own TC0 */
+    let tc0 = unsafe { &*atsamd20e15a::TC0::ptr() }.count16();
+    tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
 
     /* Enable LEDs */
     pull_pins_low(snowflake::pwmcache().get_clear_bits(time));
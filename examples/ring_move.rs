@@ -1,36 +1,46 @@
-#![feature(used)]
+#![no_main]
 #![no_std]
 
 extern crate panic_abort;
 
-#[macro_use(exception, interrupt)]
 extern crate atsamd20e15a;
 extern crate cortex_m;
 
+use atsamd20e15a::clock::{ClockConfig, ClockSource, Dfll48m, Generator, Osc8mPrescaler};
+use atsamd20e15a::delay::systick_periodic;
+use atsamd20e15a::time::U32Ext;
 use atsamd20e15a::{
-    delay_init, init_48_mhz_clock, init_gpios, init_systick, pull_pins_high, pull_pins_low,
-    setup_tc0, snowflake,
+    delay_init, entry, exception, init_gpios, interrupt, pull_pins_high, pull_pins_low, setup_tc0,
+    snowflake,
 };
 
 /* If set to true, enables a high edge on data out pin during PWM value calculation for measurement
  * via oscilloscope */
 const DEBUG: bool = false;
 
-fn main() {
+#[entry]
+fn main() -> ! {
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+
     /* ATSAMD is bitchy, let's delay a bit so we can attach with a debugger if we need to */
     delay_init();
 
-    /* Initialise clock, has its own critical section */
-    init_48_mhz_clock();
+    /* Initialise clock, CPU runs from the DFLL48M in open-loop mode */
+    ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .dfll48m(Dfll48m::OpenLoop)
+        .generator(Generator::Gclk0, ClockSource::Dfll48m, 1)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
 
     /* Initialise the used GPIOs */
-    init_gpios();
+    init_gpios(&p.PORT);
 
-    /* Initialise the SysTick timer and exception */
-    init_systick(6_000_000);
+    /* Fire the SysTick exception 8 times per second */
+    systick_periodic(8.hz());
 
     /* Setup timer interrupt with 480kHz frequency */
-    setup_tc0(100);
+    setup_tc0(&p.GCLK, &p.PM, &p.TC0, 100);
 
     let leds = snowflake::snowflake_leds();
     leds.set(0);
@@ -38,31 +48,35 @@ fn main() {
 
     /* Recalculate PWM values */
     snowflake::pwmcache().calculate_perceived(leds);
+
+    loop {
+        /* Sleep until the next interrupt */
+        cortex_m::asm::wfi();
+    }
 }
 
 /* Define an exception handler, i.e. function to call when the specific exception occurs. Here our SysTick timer
- * trips the running function */
-exception!(SYS_TICK, running, locals: {
-    time: u8 = 0;
-});
+ * trips this handler */
+#[exception]
+fn SysTick() {
+    static mut TIME: u8 = 0;
 
-fn running(l: &mut SYS_TICK::Locals) {
     let leds = &mut snowflake::snowflake_leds();
 
     if DEBUG {
         pull_pins_high(snowflake::DATAOUT);
     }
 
-    if l.time < 128 {
+    if *TIME < 128 {
         snowflake::snowflake_leds().shift_outwards();
 
-        if l.time & 4 == 4 {
+        if *TIME & 4 == 4 {
             leds.set_ring(&snowflake::SNOWFLAKE_RING::INNER, 128);
         }
     } else {
         snowflake::snowflake_leds().shift_inwards();
 
-        if l.time & 4 == 4 {
+        if *TIME & 4 == 4 {
             leds.set_ring(&snowflake::SNOWFLAKE_RING::OUTER, 128);
         }
     }
@@ -74,19 +88,17 @@ fn running(l: &mut SYS_TICK::Locals) {
         pull_pins_low(snowflake::DATAOUT);
     }
 
-    l.time += 1;
+    *TIME += 1;
 }
 
 /* Define an interrupt handler, i.e. function to call when the specific interrupt occurs. Here our
- * timer to handle the PWM trips the fade function */
-interrupt!(TC0, pwm_handler, locals: {
-    time: u8 = 0;
-});
+ * timer to handle the PWM trips this handler which calls our main fade function residing in RAM */
+#[interrupt]
+fn TC0() {
+    static mut TIME: u8 = 0;
 
-/* The interrupt handler to call our main fade function residing in RAM */
-fn pwm_handler(l: &mut TC0::Locals) {
     /* Call into handler placed in RAM to avoid flash wait states */
-    l.time = do_pwm(l.time);
+    *TIME = do_pwm(*TIME);
 }
 
 /* Place function into RAM to avoid flash wait states */
@@ -94,11 +106,9 @@ fn pwm_handler(l: &mut TC0::Locals) {
 #[inline(never)]
 /* Apply the current LED intensity of all LEDs */
 fn do_pwm(time: u8) -> u8 {
-    /* Enter critical section */
-    cortex_m::interrupt::free(|cs| {
-        let tc0 = atsamd20e15a::TC0.borrow(cs);
-        tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
-    });
+    /* Acknowledge the interrupt, INTFLAG is write-one-to-clear so we don't need to own TC0 */
+    let tc0 = unsafe { &*atsamd20e15a::TC0::ptr() }.count16();
+    tc0.intflag.write(|w| w.ovf().set_bit().err().set_bit());
 
     /* Enable LEDs */
     pull_pins_low(snowflake::pwmcache().get_clear_bits(time));
@@ -0,0 +1,64 @@
+#![no_main]
+#![no_std]
+
+extern crate panic_abort;
+
+extern crate atsamd20e15a;
+extern crate cortex_m;
+extern crate embedded_hal;
+#[macro_use]
+extern crate nb;
+
+use atsamd20e15a::clock::{ClockConfig, ClockId, ClockSource, Dfll48m, Generator, Osc8mPrescaler};
+use atsamd20e15a::gpio::GpioExt;
+use atsamd20e15a::pmux::{into_role, Pad};
+use atsamd20e15a::time::U32Ext;
+use atsamd20e15a::usart::{self, Config, Usart};
+use atsamd20e15a::{entry, interrupt, SERCOM0};
+use embedded_hal::serial::{Read, Write};
+
+#[entry]
+fn main() -> ! {
+    static mut RX: [u16; 32] = [0; 32];
+    static mut TX: [u16; 32] = [0; 32];
+
+    let p = atsamd20e15a::Peripherals::take().unwrap();
+
+    /* Initialise clock, CPU and SERCOM0 run from the DFLL48M in open-loop mode */
+    let clocks = ClockConfig::new()
+        .osc8m(Osc8mPrescaler::Div1)
+        .dfll48m(Dfll48m::OpenLoop)
+        .generator(Generator::Gclk0, ClockSource::Dfll48m, 1)
+        .route(ClockId::Sercom0Core, Generator::Gclk0)
+        .freeze(&p.SYSCTRL, &p.GCLK, &p.NVMCTRL)
+        .unwrap();
+
+    let mut port = p.PORT.split();
+
+    /* TX on PA4 (PAD0) and RX on PA5 (PAD1) of SERCOM0 */
+    let tx = into_role::<_, Pad<SERCOM0>>(port.pa4, &mut port.port);
+    let rx = into_role::<_, Pad<SERCOM0>>(port.pa5, &mut port.port);
+
+    let mut serial =
+        Usart::sercom0(p.SERCOM0, &p.PM, &clocks, Config::new(115_200.hz()), tx, rx).unwrap();
+
+    /* Let the SERCOM0 interrupt fill and drain the buffers */
+    serial.listen(RX, TX);
+
+    loop {
+        match serial.read() {
+            Ok(byte) => {
+                block!(serial.write(byte)).ok();
+            }
+            Err(nb::Error::WouldBlock) => {}
+            /* Drop characters with framing or parity errors and overflows */
+            Err(nb::Error::Other(_)) => {}
+        }
+    }
+}
+
+/* The USART has no state of its own in the handler, so just forward the IRQ */
+#[interrupt]
+fn SERCOM0() {
+    usart::handle_interrupt_sercom0();
+}
@@ -0,0 +1,362 @@
+use super::clock::{self, ClockId, Clocks};
+use super::pmux::AcAinPin;
+use super::power::{self, WakeSource};
+use super::{Interrupt, AC, NVIC, PM};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use nb;
+use void::Void;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Comparator {
+    Comp0 = 0,
+    Comp1,
+}
+
+/* AIN number of a pin, built by `Input::pin` */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct PinInput(u8);
+
+/* VDDANA * (n + 1) / 64 for n from 0 to 63, built by `Input::vscale` */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Scale(u8);
+
+/* Negative input of a comparator, the positive input is always one of the AIN pins */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Input {
+    Pin(PinInput),
+    Gnd,
+    VScale(Scale),
+    Bandgap,
+    Dac,
+}
+
+impl Input {
+    pub fn pin<P: AcAinPin>(_pin: &P) -> Input {
+        Input::Pin(PinInput(P::AIN))
+    }
+
+    /* VDDANA * (n + 1) / 64, `None` for `n` above 63 */
+    pub fn vscale(n: u8) -> Option<Input> {
+        if n < 64 {
+            Some(Input::VScale(Scale(n)))
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    Continuous,
+    /* Compare only when asked to by `read` */
+    SingleShot,
+}
+
+/* Change of the comparator output which raises the interrupt and event */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Trigger {
+    Toggle = 0,
+    Rising,
+    Falling,
+    /* End of each comparison, needed for single-shot mode */
+    EndOfComparison,
+}
+
+/* Inputs and behaviour of a comparator, continuous without hysteresis and triggering on every
+ * toggle unless changed */
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    positive: u8,
+    negative: Input,
+    mode: Mode,
+    hysteresis: bool,
+    trigger: Trigger,
+}
+
+impl Config {
+    pub fn new<P: AcAinPin>(_positive: &P, negative: Input) -> Config {
+        Config {
+            positive: P::AIN,
+            negative,
+            mode: Mode::Continuous,
+            hysteresis: false,
+            trigger: Trigger::Toggle,
+        }
+    }
+
+    /* Single-shot mode always triggers at the end of the comparison, whatever `trigger` says */
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn hysteresis(mut self, hysteresis: bool) -> Self {
+        self.hysteresis = hysteresis;
+        self
+    }
+
+    pub fn trigger(mut self, trigger: Trigger) -> Self {
+        self.trigger = trigger;
+        self
+    }
+}
+
+/* Where the positive input of comparator 0 lies between the levels of the two comparators, in
+ * window mode the inputs of both are connected to the same signal */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowState {
+    Above = 0,
+    Inside,
+    Below,
+}
+
+/* Window state which raises the window interrupt and event */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum WindowTrigger {
+    Above,
+    Inside,
+    Below,
+    Outside,
+}
+
+/* Sources of interrupts and events, numbered by their callback slot */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Comp0 = 0,
+    Comp1,
+    Window,
+}
+
+/* Bits of the events in INTENSET/INTENCLR/INTFLAG and in EVCTRL */
+const EVENT_BITS: [u8; 3] = [1 << 0, 1 << 1, 1 << 4];
+
+/* Callbacks for each event, run by `handle_interrupt` */
+static mut CALLBACKS: [Option<fn()>; 3] = [None; 3];
+
+/* Both comparators of the AC */
+pub struct Ac {
+    ac: AC,
+    /* A single-shot comparison has been started and not read yet */
+    started: [bool; 2],
+}
+
+impl Ac {
+    /* Take over the AC, which needs generic clocks routed to its digital and analog parts */
+    pub fn new(ac: AC, pm: &PM, clocks: &Clocks) -> Result<Ac, clock::Error> {
+        clocks.require(ClockId::AcDig)?;
+        clocks.require(ClockId::AcAna)?;
+
+        /* Enable clock for the AC */
+        pm.apbcmask.modify(|_, w| w.ac().set_bit());
+
+        /* Reset the AC */
+        ac.ctrla.write(|w| w.swrst().set_bit());
+
+        /* And wait */
+        while ac.statusb.read().syncbusy().bit_is_set() {}
+
+        /* Enable */
+        ac.ctrla.write(|w| w.enable().set_bit());
+
+        /* And wait */
+        while ac.statusb.read().syncbusy().bit_is_set() {}
+
+        Ok(Ac {
+            ac,
+            started: [false; 2],
+        })
+    }
+
+    /* Set up and enable `comparator` */
+    pub fn configure(&mut self, comparator: Comparator, config: Config) {
+        let n = comparator as usize;
+
+        /* COMPCTRL can only be written while the comparator is disabled */
+        self.disable(comparator);
+
+        if let Input::VScale(Scale(scale)) = config.negative {
+            self.ac.scaler[n].write(|w| unsafe { w.value().bits(scale) });
+        }
+
+        /* `read` waits for the interrupt flag of the comparison it started */
+        let trigger = if config.mode == Mode::SingleShot {
+            Trigger::EndOfComparison
+        } else {
+            config.trigger
+        };
+
+        self.ac.compctrl[n].write(|w| {
+            let w = w.single().bit(config.mode == Mode::SingleShot);
+            let w = w
+                .muxpos()
+                .bits(config.positive)
+                .hyst()
+                .bit(config.hysteresis);
+            let w = match config.negative {
+                Input::Pin(PinInput(ain)) => w.muxneg().bits(ain),
+                Input::Gnd => w.muxneg().gnd(),
+                Input::VScale(_) => w.muxneg().vscale(),
+                Input::Bandgap => w.muxneg().bandgap(),
+                Input::Dac => w.muxneg().dac(),
+            };
+            w.intsel().bits(trigger as u8)
+        });
+
+        self.ac.compctrl[n].modify(|_, w| w.enable().set_bit());
+
+        /* And wait */
+        while self.ac.statusb.read().syncbusy().bit_is_set() {}
+
+        self.started[n] = false;
+    }
+
+    pub fn disable(&mut self, comparator: Comparator) {
+        self.ac.compctrl[comparator as usize].modify(|_, w| w.enable().clear_bit());
+
+        /* And wait */
+        while self.ac.statusb.read().syncbusy().bit_is_set() {}
+    }
+
+    /* Whether the positive input of `comparator` is above the negative one. In single-shot mode
+     * the first call starts a comparison, which can't be read with its interrupt enabled */
+    pub fn read(&mut self, comparator: Comparator) -> nb::Result<bool, Void> {
+        let n = comparator as usize;
+        let bit = EVENT_BITS[n];
+
+        if self.ac.compctrl[n].read().single().bit_is_set() {
+            if !self.started[n] {
+                self.ac.intflag.write(|w| unsafe { w.bits(bit) });
+                self.ac.ctrlb.write(|w| unsafe { w.bits(1 << n) });
+                self.started[n] = true;
+                return Err(nb::Error::WouldBlock);
+            }
+
+            if self.ac.intflag.read().bits() & bit == 0 {
+                return Err(nb::Error::WouldBlock);
+            }
+
+            self.ac.intflag.write(|w| unsafe { w.bits(bit) });
+            self.started[n] = false;
+        } else if self.ac.statusb.read().bits() & (1 << n) == 0 {
+            /* The comparator is still starting up */
+            return Err(nb::Error::WouldBlock);
+        }
+
+        Ok(self.ac.statusa.read().bits() & (1 << n) != 0)
+    }
+
+    /* Use both comparators as a window, `None` turns window mode off */
+    pub fn set_window(&mut self, trigger: Option<WindowTrigger>) {
+        self.ac.winctrl.write(|w| match trigger {
+            None => w.wen0().clear_bit(),
+            Some(trigger) => {
+                let w = w.wen0().set_bit();
+                match trigger {
+                    WindowTrigger::Above => w.wintsel0().above(),
+                    WindowTrigger::Inside => w.wintsel0().inside(),
+                    WindowTrigger::Below => w.wintsel0().below(),
+                    WindowTrigger::Outside => w.wintsel0().outside(),
+                }
+            }
+        });
+
+        /* And wait */
+        while self.ac.statusb.read().syncbusy().bit_is_set() {}
+    }
+
+    pub fn window_state(&self) -> WindowState {
+        match self.ac.statusa.read().wstate0().bits() {
+            0 => WindowState::Above,
+            1 => WindowState::Inside,
+            _ => WindowState::Below,
+        }
+    }
+
+    /* Send an event to the EVSYS whenever `event` triggers */
+    pub fn set_event_output(&mut self, event: Event, enable: bool) {
+        let bit = EVENT_BITS[event as usize] as u16;
+
+        self.ac.evctrl.modify(|r, w| unsafe {
+            w.bits(if enable {
+                r.bits() | bit
+            } else {
+                r.bits() & !bit
+            })
+        });
+    }
+
+    /* Call `callback` from `handle_interrupt` whenever `event` triggers */
+    pub fn listen(&mut self, event: Event, callback: fn()) {
+        let bit = EVENT_BITS[event as usize];
+
+        /* Make sure the handler doesn't run while we swap the callback */
+        self.ac.intenclr.write(|w| unsafe { w.bits(bit) });
+        unsafe { CALLBACKS[event as usize] = Some(callback) };
+
+        /* Clear any stale interrupt and enable it */
+        self.ac.intflag.write(|w| unsafe { w.bits(bit) });
+        self.ac.intenset.write(|w| unsafe { w.bits(bit) });
+
+        interrupt::free(|cs| {
+            let nvic = NVIC.borrow(cs);
+
+            /* Enable AC IRQs */
+            nvic.enable(Interrupt::AC);
+        });
+    }
+
+    pub fn unlisten(&mut self, event: Event) {
+        let bit = EVENT_BITS[event as usize];
+
+        self.ac.intenclr.write(|w| unsafe { w.bits(bit) });
+        unsafe { CALLBACKS[event as usize] = None };
+    }
+
+    /* To be called from the interrupt handler of the AC: clears the flag of every pending event
+     * and runs its callback */
+    pub fn handle_interrupt() {
+        let ac = unsafe { &*AC::ptr() };
+
+        let pending = ac.intflag.read().bits() & ac.intenset.read().bits();
+        ac.intflag.write(|w| unsafe { w.bits(pending) });
+
+        for (event, bit) in EVENT_BITS.iter().enumerate() {
+            if pending & bit != 0 {
+                if let Some(callback) = unsafe { CALLBACKS[event] } {
+                    callback();
+                }
+            }
+        }
+    }
+
+    /* Keep the AC running in standby, which needs its generic clock to run in standby as well.
+     * The AC is briefly disabled */
+    pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+        power::ac_run_in_standby(&self.ac, run_in_standby);
+    }
+
+    /* Hand back the AC after disabling it */
+    pub fn free(self, pm: &PM) -> AC {
+        self.ac.intenclr.write(|w| unsafe { w.bits(0xFF) });
+
+        self.ac.ctrla.write(|w| w.enable().clear_bit());
+
+        /* And wait */
+        while self.ac.statusb.read().syncbusy().bit_is_set() {}
+
+        pm.apbcmask.modify(|_, w| w.ac().clear_bit());
+
+        self.ac
+    }
+}
+
+impl WakeSource for Ac {
+    fn is_armed(&self, pm: &PM) -> bool {
+        power::bus_clock_enabled(pm, &self.ac)
+            && power::nvic_enabled(Interrupt::AC)
+            && self.ac.intenset.read().bits() != 0
+    }
+}
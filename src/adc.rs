@@ -0,0 +1,518 @@
+use super::clock::{self, nvm_calibration, ClockId, Clocks};
+use super::gpio::{Pa10, Pa11, Pa2, Pa3, Pa4, Pa5, Pa6, Pa7, Pa8, Pa9, PfB};
+use super::pmux::{AinPin, NegAinPin};
+use super::power;
+use super::{Interrupt, ADC, NVIC, PM};
+
+extern crate cortex_m;
+
+use core::marker::PhantomData;
+use cortex_m::interrupt;
+use embedded_hal::adc::{Channel, OneShot};
+use nb;
+use void::Void;
+
+/* Position of the factory calibration in the NVM software calibration area */
+const LINEARITY_CAL_OFFSET: u32 = 27;
+const LINEARITY_CAL_WIDTH: u32 = 8;
+const BIAS_CAL_OFFSET: u32 = 35;
+const BIAS_CAL_WIDTH: u32 = 3;
+
+/* Highest ADC clock the prescaler may give */
+const MAX_ADC_CLOCK: u32 = 2_100_000;
+
+/* INPUTCTRL.MUXPOS of the internal inputs */
+const MUXPOS_TEMP: u8 = 0x18;
+const MUXPOS_BANDGAP: u8 = 0x19;
+const MUXPOS_SCALEDCOREVCC: u8 = 0x1A;
+const MUXPOS_SCALEDIOVCC: u8 = 0x1B;
+const MUXPOS_DAC: u8 = 0x1C;
+
+/* INPUTCTRL.MUXNEG for single-ended conversions */
+const MUXNEG_GND: u8 = 0x18;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* The number of results doesn't match the number of scanned inputs */
+    ResultCount,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reference {
+    /* 1.0V internal reference */
+    Int1V,
+    /* VDDANA / 1.48 */
+    IntVcc0,
+    /* VDDANA / 2 */
+    IntVcc1,
+    /* External reference on VREFA, i.e. PA3 */
+    ArefA,
+    /* External reference on VREFB, i.e. PA4 */
+    ArefB,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Gain {
+    X1 = 0,
+    X2,
+    X4,
+    X8,
+    X16,
+    Div2 = 0xF,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Resolution {
+    Twelve = 0,
+    Ten = 2,
+    Eight = 3,
+}
+
+/* Number of samples averaged in hardware for each result */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Averaging {
+    None = 0,
+    X2,
+    X4,
+    X8,
+    X16,
+    X32,
+    X64,
+    X128,
+    X256,
+    X512,
+    X1024,
+}
+
+/* Reference, gain and result format, 12 bit results against the 1.0V reference without gain and
+ * averaging unless changed */
+#[derive(Clone, Copy, Debug)]
+pub struct Config {
+    reference: Reference,
+    gain: Gain,
+    resolution: Resolution,
+    averaging: Averaging,
+}
+
+impl Config {
+    pub fn new() -> Config {
+        Config {
+            reference: Reference::Int1V,
+            gain: Gain::X1,
+            resolution: Resolution::Twelve,
+            averaging: Averaging::None,
+        }
+    }
+
+    /* The VREFA and VREFB pins have to be handed to the ADC by the caller */
+    pub fn reference(mut self, reference: Reference) -> Self {
+        self.reference = reference;
+        self
+    }
+
+    pub fn gain(mut self, gain: Gain) -> Self {
+        self.gain = gain;
+        self
+    }
+
+    pub fn resolution(mut self, resolution: Resolution) -> Self {
+        self.resolution = resolution;
+        self
+    }
+
+    /* Averaged results always have 12 bits, whatever the resolution */
+    pub fn averaging(mut self, averaging: Averaging) -> Self {
+        self.averaging = averaging;
+        self
+    }
+}
+
+/* Condition the window monitor looks for in each result */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Window {
+    Disabled,
+    Above(u16),
+    Below(u16),
+    Inside { lower: u16, upper: u16 },
+    Outside { lower: u16, upper: u16 },
+}
+
+/* Inputs of the ADC which aren't pins. The temperature sensor and the bandgap have to be switched
+ * on in SYSCTRL.VREF first */
+pub struct Temperature;
+pub struct Bandgap;
+/* Core supply scaled by 1/4 */
+pub struct ScaledCoreVcc;
+/* I/O supply scaled by 1/4 */
+pub struct ScaledIoVcc;
+pub struct DacOutput;
+
+/* Difference between the pins `P` and `N`, `N` being one of AIN0-7. The result is a two's
+ * complement value */
+pub struct Differential<P, N> {
+    _pins: PhantomData<(P, N)>,
+}
+
+impl<P: AinPin, N: NegAinPin> Differential<P, N> {
+    pub fn new(_positive: &P, _negative: &N) -> Differential<P, N> {
+        Differential { _pins: PhantomData }
+    }
+}
+
+/* Window monitor callback, gets the result which matched */
+static mut WINDOW_CALLBACK: Option<fn(u16)> = None;
+
+pub struct Adc {
+    adc: ADC,
+    gain: Gain,
+    /* MUXPOS and MUXNEG of a one-shot conversion which has been started and not read yet */
+    converting: Option<(u8, u8)>,
+}
+
+impl Adc {
+    /* Take over the ADC, which needs a generic clock routed to it. The prescaler is chosen to keep
+     * the ADC clock below 2.1MHz */
+    pub fn new(adc: ADC, pm: &PM, clocks: &Clocks, config: Config) -> Result<Adc, clock::Error> {
+        let clock = clocks.require(ClockId::Adc)?;
+        let prescaler = (0..8)
+            .position(|p| clock.0 >> (p + 2) <= MAX_ADC_CLOCK)
+            .expect("ADC clock too fast for the prescaler");
+
+        /* Enable clock for the ADC */
+        pm.apbcmask.modify(|_, w| w.adc().set_bit());
+
+        /* Reset the ADC */
+        adc.ctrla.write(|w| w.swrst().set_bit());
+
+        /* And wait */
+        while adc.ctrla.read().swrst().bit_is_set() {}
+        while adc.status.read().syncbusy().bit_is_set() {}
+
+        /* Load the factory calibration */
+        let linearity = nvm_calibration(LINEARITY_CAL_OFFSET, LINEARITY_CAL_WIDTH) as u8;
+        let bias = nvm_calibration(BIAS_CAL_OFFSET, BIAS_CAL_WIDTH) as u8;
+        adc.calib
+            .write(|w| unsafe { w.linearity_cal().bits(linearity).bias_cal().bits(bias) });
+
+        adc.refctrl.write(|w| match config.reference {
+            Reference::Int1V => w.refsel().int1v(),
+            Reference::IntVcc0 => w.refsel().intvcc0(),
+            Reference::IntVcc1 => w.refsel().intvcc1(),
+            Reference::ArefA => w.refsel().arefa(),
+            Reference::ArefB => w.refsel().arefb(),
+        });
+
+        /* Accumulate 2^n samples and divide back to 12 bits, the ADC shifts any bits beyond the
+         * 16th out by itself */
+        let samplenum = config.averaging as u8;
+        adc.avgctrl.write(|w| unsafe {
+            w.samplenum()
+                .bits(samplenum)
+                .adjres()
+                .bits(samplenum.min(4))
+        });
+
+        adc.ctrlb.write(|w| {
+            let w = w.prescaler().bits(prescaler as u8);
+            if config.averaging == Averaging::None {
+                w.ressel().bits(config.resolution as u8)
+            } else {
+                w.ressel()._16bit()
+            }
+        });
+
+        /* And wait */
+        while adc.status.read().syncbusy().bit_is_set() {}
+
+        /* Enable */
+        adc.ctrla.modify(|_, w| w.enable().set_bit());
+
+        /* And wait */
+        while adc.status.read().syncbusy().bit_is_set() {}
+
+        Ok(Adc {
+            adc,
+            gain: config.gain,
+            converting: None,
+        })
+    }
+
+    /* Convert `count` inputs from MUXPOS on against MUXNEG, differential if MUXNEG isn't GND */
+    fn select(&mut self, (muxpos, muxneg): (u8, u8), count: u8) {
+        let gain = self.gain as u8;
+
+        /* Let a one-shot conversion still running finish, its result is thrown away below */
+        if self.converting.take().is_some() {
+            while self.adc.intflag.read().resrdy().bit_is_clear() {}
+        }
+
+        self.adc
+            .ctrlb
+            .modify(|_, w| w.diffmode().bit(muxneg != MUXNEG_GND));
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc.inputctrl.write(|w| unsafe {
+            w.muxpos()
+                .bits(muxpos)
+                .muxneg()
+                .bits(muxneg)
+                .inputscan()
+                .bits(count - 1)
+                .inputoffset()
+                .bits(0)
+                .gain()
+                .bits(gain)
+        });
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        /* Throw away a result of the previous input */
+        self.adc.intflag.write(|w| w.resrdy().set_bit());
+    }
+
+    fn start(&mut self) {
+        self.adc.swtrig.write(|w| w.start().set_bit());
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+    }
+
+    fn result(&self) -> u16 {
+        /* RESULT is read synchronised */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc.result.read().result().bits()
+    }
+
+    /* Convert all of `pins` in one go into `results`, which must have exactly one slot per pin */
+    pub fn scan<PINS: ScanPins>(
+        &mut self,
+        _pins: &mut PINS,
+        results: &mut [u16],
+    ) -> Result<(), Error> {
+        if results.len() != PINS::COUNT as usize {
+            return Err(Error::ResultCount);
+        }
+
+        self.select((PINS::FIRST, MUXNEG_GND), PINS::COUNT);
+
+        for result in results.iter_mut() {
+            self.start();
+            while self.adc.intflag.read().resrdy().bit_is_clear() {}
+            *result = self.result();
+        }
+
+        Ok(())
+    }
+
+    /* Keep converting `pin`, e.g. for the window monitor */
+    pub fn start_free_running<PIN: Channel<Adc, ID = (u8, u8)>>(&mut self, _pin: &mut PIN) {
+        self.select(PIN::channel(), 1);
+
+        self.adc.ctrlb.modify(|_, w| w.freerun().set_bit());
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.start();
+    }
+
+    pub fn stop_free_running(&mut self) {
+        self.adc.ctrlb.modify(|_, w| w.freerun().clear_bit());
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+    }
+
+    pub fn set_window(&mut self, window: Window) {
+        let (lower, upper) = match window {
+            Window::Disabled => (0, 0),
+            Window::Above(lower) => (lower, 0),
+            Window::Below(upper) => (0, upper),
+            Window::Inside { lower, upper } | Window::Outside { lower, upper } => (lower, upper),
+        };
+
+        self.adc.winlt.write(|w| unsafe { w.winlt().bits(lower) });
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc.winut.write(|w| unsafe { w.winut().bits(upper) });
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        self.adc.winctrl.write(|w| match window {
+            Window::Disabled => w.winmode().disable(),
+            Window::Above(_) => w.winmode().mode1(),
+            Window::Below(_) => w.winmode().mode2(),
+            Window::Inside { .. } => w.winmode().mode3(),
+            Window::Outside { .. } => w.winmode().mode4(),
+        });
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+    }
+
+    /* Have `callback` called with each result matching the window */
+    pub fn listen_window(&mut self, callback: fn(u16)) {
+        let adc = &self.adc;
+
+        interrupt::free(|cs| {
+            let nvic = NVIC.borrow(cs);
+
+            unsafe { WINDOW_CALLBACK = Some(callback) };
+
+            adc.intflag.write(|w| w.winmon().set_bit());
+            adc.intenset.write(|w| w.winmon().set_bit());
+
+            /* Enable ADC IRQs */
+            nvic.enable(Interrupt::ADC);
+        });
+    }
+
+    pub fn unlisten_window(&mut self) {
+        let adc = &self.adc;
+
+        interrupt::free(|_| {
+            adc.intenclr.write(|w| w.winmon().set_bit());
+            unsafe { WINDOW_CALLBACK = None };
+        });
+    }
+
+    /* To be called from the interrupt handler of the ADC */
+    pub fn handle_interrupt() {
+        let adc = unsafe { &*ADC::ptr() };
+
+        if adc.intflag.read().winmon().bit_is_set() {
+            adc.intflag.write(|w| w.winmon().set_bit());
+
+            /* RESULT is read synchronised */
+            while adc.status.read().syncbusy().bit_is_set() {}
+
+            let result = adc.result.read().result().bits();
+            if let Some(callback) = unsafe { WINDOW_CALLBACK } {
+                callback(result);
+            }
+        }
+    }
+
+    /* Keep the ADC running in standby, which needs its generic clock to run in standby as well.
+     * The ADC is briefly disabled */
+    pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+        power::adc_run_in_standby(&self.adc, run_in_standby);
+    }
+
+    /* Hand back the ADC after disabling it */
+    pub fn free(self, pm: &PM) -> ADC {
+        self.adc.intenclr.write(|w| unsafe { w.bits(0xFF) });
+
+        self.adc.ctrla.modify(|_, w| w.enable().clear_bit());
+
+        /* And wait */
+        while self.adc.status.read().syncbusy().bit_is_set() {}
+
+        pm.apbcmask.modify(|_, w| w.adc().clear_bit());
+
+        self.adc
+    }
+}
+
+impl<PIN: Channel<Adc, ID = (u8, u8)>> OneShot<Adc, u16, PIN> for Adc {
+    type Error = Void;
+
+    fn read(&mut self, _pin: &mut PIN) -> nb::Result<u16, Void> {
+        let channel = PIN::channel();
+
+        if self.converting != Some(channel) {
+            self.select(channel, 1);
+            self.start();
+            self.converting = Some(channel);
+        }
+
+        if self.adc.intflag.read().resrdy().bit_is_clear() {
+            return Err(nb::Error::WouldBlock);
+        }
+
+        self.converting = None;
+        Ok(self.result())
+    }
+}
+
+macro_rules! channels {
+    ($($Input:ty: $id:expr,)+) => {
+        $(
+            impl Channel<Adc> for $Input {
+                type ID = (u8, u8);
+
+                fn channel() -> (u8, u8) {
+                    $id
+                }
+            }
+        )+
+    };
+}
+
+channels!(
+    Pa2<PfB>: (<Pa2<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa3<PfB>: (<Pa3<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa4<PfB>: (<Pa4<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa5<PfB>: (<Pa5<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa6<PfB>: (<Pa6<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa7<PfB>: (<Pa7<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa8<PfB>: (<Pa8<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa9<PfB>: (<Pa9<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa10<PfB>: (<Pa10<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Pa11<PfB>: (<Pa11<PfB> as AinPin>::AIN, MUXNEG_GND),
+    Temperature: (MUXPOS_TEMP, MUXNEG_GND),
+    Bandgap: (MUXPOS_BANDGAP, MUXNEG_GND),
+    ScaledCoreVcc: (MUXPOS_SCALEDCOREVCC, MUXNEG_GND),
+    ScaledIoVcc: (MUXPOS_SCALEDIOVCC, MUXNEG_GND),
+    DacOutput: (MUXPOS_DAC, MUXNEG_GND),
+);
+
+/* Analog pins with consecutive AIN numbers which `Adc::scan` converts in one go, e.g.
+ * `(Pa4<PfB>, Pa5<PfB>, Pa6<PfB>)`. There's no AIN2 and AIN3 on this package, so scans stay
+ * within AIN0-1, AIN4-7 or AIN16-19 */
+pub trait ScanPins {
+    const FIRST: u8;
+    const COUNT: u8;
+}
+
+macro_rules! scan_pins {
+    ($(($First:ident $(, $PAi:ident)+): $count:expr,)+) => {
+        $(
+            impl ScanPins for ($First<PfB>, $($PAi<PfB>),+) {
+                const FIRST: u8 = <$First<PfB> as AinPin>::AIN;
+                const COUNT: u8 = $count;
+            }
+        )+
+    };
+}
+
+scan_pins!(
+    (Pa2, Pa3): 2,
+    (Pa4, Pa5): 2,
+    (Pa5, Pa6): 2,
+    (Pa6, Pa7): 2,
+    (Pa8, Pa9): 2,
+    (Pa9, Pa10): 2,
+    (Pa10, Pa11): 2,
+    (Pa4, Pa5, Pa6): 3,
+    (Pa5, Pa6, Pa7): 3,
+    (Pa8, Pa9, Pa10): 3,
+    (Pa9, Pa10, Pa11): 3,
+    (Pa4, Pa5, Pa6, Pa7): 4,
+    (Pa8, Pa9, Pa10, Pa11): 4,
+);
+
+impl<P: AinPin, N: NegAinPin> Channel<Adc> for Differential<P, N> {
+    type ID = (u8, u8);
+
+    fn channel() -> (u8, u8) {
+        (P::AIN, N::AIN)
+    }
+}
@@ -0,0 +1,240 @@
+use super::clock::{self, ClockId, Clocks};
+use super::eic::{Eic, Sense};
+use super::evsys::{self, Channel, Edge, EventSystem, Path, User};
+use super::pmux::ExtIntPin;
+use super::power;
+use super::time::Hertz;
+use super::timer::{COUNT16_COUNT_ADDR, PRESCALERS};
+use super::{PM, TC0, TC1, TC2, TC3, TC4, TC5};
+
+use nb;
+
+/* Addresses of the CC registers for read synchronisation */
+const COUNT16_CC_ADDR: [u8; 2] = [0x18, 0x1A];
+
+/* What the TC does with the events of the pin */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /* Capture the period into CC0 and the high time into CC1 */
+    PeriodPulseWidth,
+    /* Capture the high time into CC0 and the period into CC1 */
+    PulseWidthPeriod,
+    /* Count the rising edges */
+    Count,
+    /* Start counting on the first rising edge */
+    Start,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* A new value was captured before the previous one has been read */
+    Overflow,
+}
+
+/* Period and high time of the measured signal in ticks of `Capture::tick_frequency` */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Measurement {
+    pub period: u16,
+    pub pulse_width: u16,
+}
+
+/* Largest prescaler which still lets the TC count at least at `tick`, for the longest range at
+ * the requested resolution */
+fn prescaler_for_tick(clock: Hertz, tick: Hertz) -> u8 {
+    PRESCALERS
+        .iter()
+        .rposition(|prescaler| clock.0 / prescaler >= tick.0)
+        .unwrap_or(0) as u8
+}
+
+/* 16 bit TC fed by the EIC events of a pin through an EVSYS channel, so edges are measured or
+ * counted without any interrupts */
+pub struct Capture<TC> {
+    tc: TC,
+    mode: Mode,
+    tick: Hertz,
+}
+
+macro_rules! capture {
+    ($($TC:ident: ($tc:ident, $n:expr, $id:ident),)+) => {
+        $(
+            impl Capture<$TC> {
+                /* Measure the signal on `pin` with the TC counting at least at `resolution`,
+                 * as far as its generic clock allows, and the events travelling on EVSYS
+                 * `channel`. The EXTINT line of the pin is switched to high level detection with
+                 * event output */
+                pub fn $tc<P: ExtIntPin, F: Into<Hertz>>(
+                    tc: $TC,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    eic: &mut Eic,
+                    evsys: &mut EventSystem,
+                    pin: &P,
+                    channel: Channel,
+                    mode: Mode,
+                    resolution: F,
+                ) -> Result<Capture<$TC>, clock::Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+                    let prescaler = prescaler_for_tick(clock, resolution.into());
+
+                    /* Let the pin level become the event signal */
+                    eic.configure(pin, Sense::High, false);
+                    eic.set_event_output(pin, true);
+
+                    /* Capture needs the level, so take the asynchronous path */
+                    evsys.connect(
+                        channel,
+                        evsys::Generator::ExtInt(P::EXTINT),
+                        User::Tc($n),
+                        Path::Asynchronous,
+                        Edge::None,
+                        clocks,
+                    )?;
+
+                    /* Enable clock for the TC */
+                    pm.apbcmask.modify(|_, w| w.$tc().set_bit());
+
+                    {
+                        let count16 = tc.count16();
+
+                        /* Reset the TC */
+                        count16.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while count16.status.read().syncbusy().bit_is_set() {}
+
+                        count16
+                            .ctrla
+                            .write(|w| w.mode().count16().prescaler().bits(prescaler));
+
+                        /* Capture into both CC registers for the pulse measurements */
+                        if mode == Mode::PeriodPulseWidth || mode == Mode::PulseWidthPeriod {
+                            count16.ctrlc.write(|w| w.cpten0().set_bit().cpten1().set_bit());
+
+                            /* And wait */
+                            while count16.status.read().syncbusy().bit_is_set() {}
+                        }
+
+                        /* Take events as input */
+                        count16.evctrl.write(|w| {
+                            let w = w.tcei().set_bit();
+                            match mode {
+                                Mode::PeriodPulseWidth => w.evact().ppw(),
+                                Mode::PulseWidthPeriod => w.evact().pwp(),
+                                Mode::Count => w.evact().count(),
+                                Mode::Start => w.evact().start(),
+                            }
+                        });
+
+                        /* Enable */
+                        count16.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while count16.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(Capture {
+                        tc,
+                        mode,
+                        tick: Hertz(clock.0 / PRESCALERS[prescaler as usize]),
+                    })
+                }
+
+                /* Frequency the TC counts at, i.e. the unit of all measurements */
+                pub fn tick_frequency(&self) -> Hertz {
+                    self.tick
+                }
+
+                /* Latest period and high time of the signal, once both have been captured */
+                pub fn read(&mut self) -> nb::Result<Measurement, Error> {
+                    let count16 = self.tc.count16();
+                    let flags = count16.intflag.read();
+
+                    /* ERR means a capture got overwritten before we read it */
+                    if flags.err().bit_is_set() {
+                        count16.intflag.write(|w| w.err().set_bit());
+                        return Err(nb::Error::Other(Error::Overflow));
+                    }
+
+                    if flags.mc0().bit_is_clear() || flags.mc1().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    /* Reading the captured values clears MC0 and MC1 */
+                    self.sync_read(COUNT16_CC_ADDR[0]);
+                    let cc0 = count16.cc[0].read().cc().bits();
+                    self.sync_read(COUNT16_CC_ADDR[1]);
+                    let cc1 = count16.cc[1].read().cc().bits();
+
+                    Ok(match self.mode {
+                        Mode::PulseWidthPeriod => Measurement {
+                            period: cc1,
+                            pulse_width: cc0,
+                        },
+                        _ => Measurement {
+                            period: cc0,
+                            pulse_width: cc1,
+                        },
+                    })
+                }
+
+                /* Request a synchronised read of the register at `addr` */
+                fn sync_read(&self, addr: u8) {
+                    let count16 = self.tc.count16();
+
+                    count16
+                        .readreq
+                        .write(|w| unsafe { w.addr().bits(addr) }.rreq().set_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Number of edges counted or ticks since the start edge */
+                pub fn count(&self) -> u16 {
+                    self.sync_read(COUNT16_COUNT_ADDR);
+                    self.tc.count16().count.read().count().bits()
+                }
+
+                pub fn reset_count(&mut self) {
+                    let count16 = self.tc.count16();
+
+                    count16.count.write(|w| unsafe { w.count().bits(0) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Keep the capture running in standby, which needs its generic clock to run in
+                 * standby as well. The TC is briefly disabled and keeps its count */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::tc_run_in_standby(&self.tc, run_in_standby);
+                }
+
+                /* Hand back the TC after disabling it and detaching it from the EVSYS */
+                pub fn free(self, pm: &PM, evsys: &mut EventSystem) -> $TC {
+                    let count16 = self.tc.count16();
+
+                    count16.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    evsys.disconnect(User::Tc($n));
+                    pm.apbcmask.modify(|_, w| w.$tc().clear_bit());
+
+                    self.tc
+                }
+            }
+        )+
+    };
+}
+
+capture!(
+    TC0: (tc0, 0, Tc0Tc1),
+    TC1: (tc1, 1, Tc0Tc1),
+    TC2: (tc2, 2, Tc2Tc3),
+    TC3: (tc3, 3, Tc2Tc3),
+    TC4: (tc4, 4, Tc4Tc5),
+    TC5: (tc5, 5, Tc4Tc5),
+);
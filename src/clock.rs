@@ -0,0 +1,625 @@
+use super::delay;
+use super::time::Hertz;
+use super::{GCLK, NVMCTRL, SYSCTRL};
+
+use core::ptr;
+
+/* Start of the NVM software calibration area */
+const NVM_SW_CALIB_AREA: u32 = 0x0080_6020;
+
+/* Position and width of the factory calibration values we use in the NVM software calibration area */
+const OSC32K_CAL_OFFSET: u32 = 38;
+const OSC32K_CAL_WIDTH: u32 = 7;
+const DFLL48M_COARSE_CAL_OFFSET: u32 = 58;
+const DFLL48M_COARSE_CAL_WIDTH: u32 = 6;
+
+/* Nominal output frequencies of the internal oscillators */
+const OSC8M_FREQ: u32 = 8_000_000;
+const OSC32K_FREQ: u32 = 32_768;
+const DFLL48M_FREQ: u32 = 48_000_000;
+
+/* Multiplier locking the DFLL48M to a 32.768 kHz reference, the closest we can get to 48 MHz */
+const DFLL48M_XOSC32K_MUL: u16 = 1464;
+
+/* Number of polls of the DFLL48M lock flags before giving up, far beyond the usual lock time */
+const DFLL48M_LOCK_TIMEOUT: u32 = 1_000_000;
+
+/* Highest frequency any generic clock generator may run at */
+const MAX_GCLK_FREQ: u32 = 48_000_000;
+
+/* Highest CPU frequency for each number of flash wait states, valid for VDD between 2.7V and 3.63V */
+const RWS_MAX_FREQ: [u32; 2] = [24_000_000, 48_000_000];
+
+/* Read a field of the NVM software calibration area */
+pub(crate) fn nvm_calibration(offset: u32, width: u32) -> u32 {
+    let low = unsafe { ptr::read_volatile(NVM_SW_CALIB_AREA as *const u32) } as u64;
+    let high = unsafe { ptr::read_volatile((NVM_SW_CALIB_AREA + 4) as *const u32) } as u64;
+
+    ((((high << 32) | low) >> offset) & ((1 << width) - 1)) as u32
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* A generator uses a clock source which hasn't been enabled */
+    SourceNotEnabled(ClockSource),
+    /* A clock was routed from or referenced a generator which hasn't been set up */
+    GeneratorNotEnabled(Generator),
+    /* GCLK1 can't be fed from its own output */
+    InvalidSource(Generator),
+    /* The divider doesn't fit into the DIV field of the generator */
+    InvalidDivider(Generator),
+    /* The DFLL48M reference must come from GCLK1-7 and must not depend on the DFLL48M itself */
+    InvalidReference(Generator),
+    /* A generator or the DFLL48M would exceed 48 MHz */
+    FrequencyTooHigh,
+    /* The DFLL48M didn't achieve coarse and fine lock to its reference in closed-loop mode */
+    DfllLockTimeout,
+    /* A peripheral driver needs a generic clock on a channel which hasn't been routed */
+    NotRouted(ClockId),
+    /* The generic clock routed to a peripheral channel is too slow for the requested speed */
+    FrequencyTooLow(ClockId),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    Gclk0,
+    Gclk1,
+    Gclk2,
+    Gclk3,
+    Gclk4,
+    Gclk5,
+    Gclk6,
+    Gclk7,
+}
+
+const GENERATORS: [Generator; 8] = [
+    Generator::Gclk0,
+    Generator::Gclk1,
+    Generator::Gclk2,
+    Generator::Gclk3,
+    Generator::Gclk4,
+    Generator::Gclk5,
+    Generator::Gclk6,
+    Generator::Gclk7,
+];
+
+impl Generator {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+
+    /* Largest value the DIV field of this generator can hold */
+    fn max_divider(self) -> u16 {
+        match self {
+            Generator::Gclk1 => 0xFFFF,
+            Generator::Gclk2 => 0x1F,
+            _ => 0xFF,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockSource {
+    Xosc,
+    Gclk1,
+    OscUlp32k,
+    Osc32k,
+    Xosc32k,
+    Osc8m,
+    Dfll48m,
+}
+
+impl ClockSource {
+    /* Value of the GENCTRL.SRC field selecting this source */
+    fn src(self) -> u8 {
+        match self {
+            ClockSource::Xosc => 0,
+            ClockSource::Gclk1 => 2,
+            ClockSource::OscUlp32k => 3,
+            ClockSource::Osc32k => 4,
+            ClockSource::Xosc32k => 5,
+            ClockSource::Osc8m => 6,
+            ClockSource::Dfll48m => 7,
+        }
+    }
+}
+
+/* Peripheral clock channels of the E variant, numbered like CLKCTRL.ID */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ClockId {
+    Dfll48m = 0,
+    Wdt,
+    Rtc,
+    Eic,
+    EvsysChannel0,
+    EvsysChannel1,
+    EvsysChannel2,
+    EvsysChannel3,
+    EvsysChannel4,
+    EvsysChannel5,
+    EvsysChannel6,
+    EvsysChannel7,
+    SercomSlow,
+    Sercom0Core,
+    Sercom1Core,
+    Sercom2Core,
+    Sercom3Core,
+    Tc0Tc1 = 19,
+    Tc2Tc3,
+    Tc4Tc5,
+    Adc = 23,
+    AcDig,
+    AcAna,
+    Dac,
+}
+
+const NUM_CLOCK_IDS: usize = 27;
+
+impl ClockId {
+    pub fn id(self) -> u8 {
+        self as u8
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Osc8mPrescaler {
+    Div1,
+    Div2,
+    Div4,
+    Div8,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum XoscMode {
+    /* A crystal is connected between XIN and XOUT */
+    Crystal,
+    /* An external clock signal is fed into XIN */
+    ExternalClock,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Dfll48m {
+    /* Free running from the factory calibration, nominally 48 MHz */
+    OpenLoop,
+    /* Locked to `multiplier` times the output of the `reference` generator */
+    ClosedLoop {
+        reference: Generator,
+        multiplier: u16,
+    },
+}
+
+#[derive(Clone, Copy)]
+struct GeneratorConfig {
+    source: ClockSource,
+    divider: u16,
+}
+
+/* Description of the whole clock tree which is only applied to the hardware by `freeze` */
+pub struct ClockConfig {
+    osc8m: Osc8mPrescaler,
+    osc32k: bool,
+    xosc32k: Option<XoscMode>,
+    xosc: Option<(Hertz, XoscMode)>,
+    dfll48m: Option<Dfll48m>,
+    generators: [Option<GeneratorConfig>; 8],
+    routes: [Option<Generator>; NUM_CLOCK_IDS],
+}
+
+impl ClockConfig {
+    /* Start out with the reset configuration: GCLK0 running from OSC8M divided by 8 */
+    pub fn new() -> ClockConfig {
+        let mut generators = [None; 8];
+        generators[0] = Some(GeneratorConfig {
+            source: ClockSource::Osc8m,
+            divider: 1,
+        });
+
+        ClockConfig {
+            osc8m: Osc8mPrescaler::Div8,
+            osc32k: false,
+            xosc32k: None,
+            xosc: None,
+            dfll48m: None,
+            generators,
+            routes: [None; NUM_CLOCK_IDS],
+        }
+    }
+
+    pub fn osc8m(mut self, prescaler: Osc8mPrescaler) -> Self {
+        self.osc8m = prescaler;
+        self
+    }
+
+    pub fn osc32k(mut self) -> Self {
+        self.osc32k = true;
+        self
+    }
+
+    pub fn xosc32k(mut self, mode: XoscMode) -> Self {
+        self.xosc32k = Some(mode);
+        self
+    }
+
+    pub fn xosc<F: Into<Hertz>>(mut self, freq: F, mode: XoscMode) -> Self {
+        self.xosc = Some((freq.into(), mode));
+        self
+    }
+
+    pub fn dfll48m(mut self, mode: Dfll48m) -> Self {
+        self.dfll48m = Some(mode);
+        self
+    }
+
+    /* Run the DFLL48M in closed-loop mode at 47.972 MHz, locked to the 32.768 kHz crystal which
+     * is fed to it through generator `reference` */
+    pub fn dfll48m_from_xosc32k(self, reference: Generator) -> Self {
+        self.xosc32k(XoscMode::Crystal)
+            .generator(reference, ClockSource::Xosc32k, 1)
+            .dfll48m(Dfll48m::ClosedLoop {
+                reference,
+                multiplier: DFLL48M_XOSC32K_MUL,
+            })
+    }
+
+    /* Feed `source` divided by `divider` into generator `gen` */
+    pub fn generator(mut self, gen: Generator, source: ClockSource, divider: u16) -> Self {
+        self.generators[gen.id() as usize] = Some(GeneratorConfig { source, divider });
+        self
+    }
+
+    /* Clock the peripheral channel `id` from generator `gen` */
+    pub fn route(mut self, id: ClockId, gen: Generator) -> Self {
+        self.routes[id.id() as usize] = Some(gen);
+        self
+    }
+
+    fn generator_config(&self, gen: Generator) -> Result<GeneratorConfig, Error> {
+        self.generators[gen.id() as usize].ok_or(Error::GeneratorNotEnabled(gen))
+    }
+
+    /* Whether the output of generator `gen` is derived from the DFLL48M */
+    fn uses_dfll48m(&self, gen: Generator) -> bool {
+        match self.generators[gen.id() as usize] {
+            Some(GeneratorConfig {
+                source: ClockSource::Dfll48m,
+                ..
+            }) => true,
+            Some(GeneratorConfig {
+                source: ClockSource::Gclk1,
+                ..
+            }) => gen != Generator::Gclk1 && self.uses_dfll48m(Generator::Gclk1),
+            _ => false,
+        }
+    }
+
+    fn source_freq(&self, source: ClockSource) -> Result<Hertz, Error> {
+        match source {
+            ClockSource::Osc8m => Ok(Hertz(OSC8M_FREQ >> self.osc8m as u32)),
+            ClockSource::OscUlp32k => Ok(Hertz(OSC32K_FREQ)),
+            ClockSource::Osc32k if self.osc32k => Ok(Hertz(OSC32K_FREQ)),
+            ClockSource::Xosc32k if self.xosc32k.is_some() => Ok(Hertz(OSC32K_FREQ)),
+            ClockSource::Xosc => match self.xosc {
+                Some((freq, _)) => Ok(freq),
+                None => Err(Error::SourceNotEnabled(source)),
+            },
+            ClockSource::Gclk1 => self.generator_freq(Generator::Gclk1),
+            ClockSource::Dfll48m => match self.dfll48m {
+                Some(Dfll48m::OpenLoop) => Ok(Hertz(DFLL48M_FREQ)),
+                Some(Dfll48m::ClosedLoop {
+                    reference,
+                    multiplier,
+                }) => {
+                    if reference == Generator::Gclk0 || self.uses_dfll48m(reference) {
+                        return Err(Error::InvalidReference(reference));
+                    }
+
+                    let freq = self.generator_freq(reference)?.0 as u64 * multiplier as u64;
+                    if freq > DFLL48M_FREQ as u64 {
+                        return Err(Error::FrequencyTooHigh);
+                    }
+
+                    Ok(Hertz(freq as u32))
+                }
+                None => Err(Error::SourceNotEnabled(source)),
+            },
+            _ => Err(Error::SourceNotEnabled(source)),
+        }
+    }
+
+    fn generator_freq(&self, gen: Generator) -> Result<Hertz, Error> {
+        let config = self.generator_config(gen)?;
+
+        if gen == Generator::Gclk1 && config.source == ClockSource::Gclk1 {
+            return Err(Error::InvalidSource(gen));
+        }
+
+        if config.divider > gen.max_divider() {
+            return Err(Error::InvalidDivider(gen));
+        }
+
+        /* Both 0 and 1 mean undivided */
+        let freq = self.source_freq(config.source)?.0 / config.divider.max(1) as u32;
+        if freq > MAX_GCLK_FREQ {
+            return Err(Error::FrequencyTooHigh);
+        }
+
+        Ok(Hertz(freq))
+    }
+
+    /* Apply the configuration to the hardware and return the resulting frequencies */
+    pub fn freeze(
+        self,
+        sysctrl: &SYSCTRL,
+        gclk: &GCLK,
+        nvmctrl: &NVMCTRL,
+    ) -> Result<Clocks, Error> {
+        /* Work out all frequencies first so we don't leave a half configured clock tree behind */
+        let mut clocks = Clocks {
+            gclk: [None; 8],
+            routes: self.routes,
+        };
+        for gen in GENERATORS.iter() {
+            if self.generators[gen.id() as usize].is_some() {
+                clocks.gclk[gen.id() as usize] = Some(self.generator_freq(*gen)?);
+            }
+        }
+        for gen in self.routes.iter().filter_map(|gen| *gen) {
+            self.generator_config(gen)?;
+        }
+
+        self.enable_oscillators(sysctrl);
+
+        /* Generators which don't depend on the DFLL48M first, GCLK1 ahead of the ones it may feed */
+        for gen in GENERATORS[1..].iter() {
+            if !self.uses_dfll48m(*gen) {
+                self.enable_generator(gclk, *gen);
+            }
+        }
+
+        if let Some(mode) = self.dfll48m {
+            self.enable_dfll48m(sysctrl, gclk, mode)?;
+        }
+
+        for gen in GENERATORS[1..].iter() {
+            if self.uses_dfll48m(*gen) {
+                self.enable_generator(gclk, *gen);
+            }
+        }
+
+        /* Raise the flash wait states before speeding up the CPU and lower them after slowing it down */
+        let rws = clocks.rws();
+        if rws > nvmctrl.ctrlb.read().rws().bits() {
+            nvmctrl.ctrlb.modify(|_, w| unsafe { w.rws().bits(rws) });
+        }
+
+        self.enable_generator(gclk, Generator::Gclk0);
+
+        nvmctrl.ctrlb.modify(|_, w| unsafe { w.rws().bits(rws) });
+
+        /* Let the delay functions know how fast we're running now */
+        delay::set_core_frequency(clocks.gclk0());
+
+        /* Hook up the requested peripheral channels */
+        for (id, gen) in self.routes.iter().enumerate() {
+            if let Some(gen) = *gen {
+                gclk.clkctrl.write(|w| unsafe {
+                    w.id().bits(id as u8).gen().bits(gen.id()).clken().set_bit()
+                });
+
+                /* Wait, again... */
+                while gclk.status.read().syncbusy().bit_is_set() {}
+            }
+        }
+
+        Ok(clocks)
+    }
+
+    fn enable_oscillators(&self, sysctrl: &SYSCTRL) {
+        /* Set prescaler and disable on-demand mode of 8 MHz oscillator, it's always running */
+        sysctrl
+            .osc8m
+            .modify(|_, w| unsafe { w.presc().bits(self.osc8m as u8).ondemand().clear_bit() });
+
+        if self.osc32k {
+            /* Load factory calibration and enable the 32 kHz output */
+            let calib = nvm_calibration(OSC32K_CAL_OFFSET, OSC32K_CAL_WIDTH) as u8;
+            sysctrl.osc32k.write(|w| unsafe {
+                w.calib()
+                    .bits(calib)
+                    .startup()
+                    .bits(0x4)
+                    .en32k()
+                    .set_bit()
+                    .enable()
+                    .set_bit()
+            });
+
+            /* Wait for it to stabilise */
+            while sysctrl.pclksr.read().osc32krdy().bit_is_clear() {}
+        }
+
+        if let Some(mode) = self.xosc32k {
+            /* Enable the 32 kHz output and give the crystal plenty of time to start up */
+            sysctrl.xosc32k.write(|w| unsafe {
+                w.startup()
+                    .bits(0x5)
+                    .xtalen()
+                    .bit(mode == XoscMode::Crystal)
+                    .en32k()
+                    .set_bit()
+            });
+            sysctrl.xosc32k.modify(|_, w| w.enable().set_bit());
+
+            /* Wait for it to stabilise */
+            while sysctrl.pclksr.read().xosc32krdy().bit_is_clear() {}
+        }
+
+        if let Some((freq, mode)) = self.xosc {
+            /* Pick the amplifier gain recommended for the crystal frequency */
+            let gain = match freq.0 {
+                0..=2_000_000 => 0,
+                2_000_001..=4_000_000 => 1,
+                4_000_001..=8_000_000 => 2,
+                8_000_001..=16_000_000 => 3,
+                _ => 4,
+            };
+
+            sysctrl.xosc.write(|w| unsafe {
+                w.startup()
+                    .bits(0x5)
+                    .gain()
+                    .bits(gain)
+                    .xtalen()
+                    .bit(mode == XoscMode::Crystal)
+            });
+            sysctrl.xosc.modify(|_, w| w.enable().set_bit());
+
+            /* Wait for it to stabilise */
+            while sysctrl.pclksr.read().xoscrdy().bit_is_clear() {}
+        }
+    }
+
+    fn enable_dfll48m(&self, sysctrl: &SYSCTRL, gclk: &GCLK, mode: Dfll48m) -> Result<(), Error> {
+        if let Dfll48m::ClosedLoop { reference, .. } = mode {
+            /* Set up the reference generator as input for DFLL */
+            gclk.clkctrl.write(|w| unsafe {
+                w.id()
+                    .dfll48m()
+                    .gen()
+                    .bits(reference.id())
+                    .clken()
+                    .set_bit()
+            });
+
+            /* Wait, again... */
+            while gclk.status.read().syncbusy().bit_is_set() {}
+        }
+
+        /* Disable on-demand mode of DFLL, otherwise the other registers can't be written */
+        sysctrl.dfllctrl.write(|w| w.ondemand().clear_bit());
+
+        /* Wait, again... */
+        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+
+        /* Start from the factory calibration, 0x3F means none has been programmed */
+        let coarse = match nvm_calibration(DFLL48M_COARSE_CAL_OFFSET, DFLL48M_COARSE_CAL_WIDTH) {
+            0x3F => 0x1F,
+            coarse => coarse as u8,
+        };
+        sysctrl
+            .dfllval
+            .write(|w| unsafe { w.coarse().bits(coarse).fine().bits(0x200) });
+
+        /* Wait, again... */
+        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+
+        match mode {
+            Dfll48m::OpenLoop => {
+                /* Fire up DFLL */
+                sysctrl.dfllctrl.modify(|_, w| w.enable().set_bit());
+
+                /* Wait, again... */
+                while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+            }
+            Dfll48m::ClosedLoop { multiplier, .. } => {
+                /* Set multiplicator for DFLL, with at most half the maximum step sizes as recommended */
+                sysctrl.dfllmul.write(|w| unsafe {
+                    w.cstep().bits(31).fstep().bits(511).mul().bits(multiplier)
+                });
+
+                /* Wait, again... */
+                while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+
+                /* Fire up DFLL in closed-loop mode */
+                sysctrl
+                    .dfllctrl
+                    .modify(|_, w| w.mode().set_bit().enable().set_bit());
+
+                /* Wait for the DFLL to lock, coarse first and then fine, but not forever since a
+                 * missing or broken reference means it never will */
+                let mut timeout = DFLL48M_LOCK_TIMEOUT;
+                while sysctrl.pclksr.read().dflllckc().bit_is_clear()
+                    || sysctrl.pclksr.read().dflllckf().bit_is_clear()
+                {
+                    if timeout == 0 {
+                        /* Stop it again so nothing gets clocked by a DFLL hunting for its reference */
+                        sysctrl.dfllctrl.modify(|_, w| w.enable().clear_bit());
+                        return Err(Error::DfllLockTimeout);
+                    }
+                    timeout -= 1;
+                }
+
+                /* Wait, again... */
+                while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn enable_generator(&self, gclk: &GCLK, gen: Generator) {
+        let config = match self.generators[gen.id() as usize] {
+            Some(config) => config,
+            None => return,
+        };
+
+        /* Set up divisor on clock generator */
+        gclk.gendiv
+            .write(|w| unsafe { w.div().bits(config.divider).id().bits(gen.id()) });
+
+        /* Wait, again... */
+        while gclk.status.read().syncbusy().bit_is_set() {}
+
+        /* Set up source for clock generator, odd dividers need duty cycle correction */
+        gclk.genctrl.write(|w| unsafe {
+            w.id()
+                .bits(gen.id())
+                .src()
+                .bits(config.source.src())
+                .idc()
+                .bit(config.divider & 1 == 1)
+                .genen()
+                .set_bit()
+        });
+
+        /* Wait, again... */
+        while gclk.status.read().syncbusy().bit_is_set() {}
+    }
+}
+
+/* Frequencies of the clock tree as set up by `ClockConfig::freeze` */
+#[derive(Clone, Copy)]
+pub struct Clocks {
+    gclk: [Option<Hertz>; 8],
+    routes: [Option<Generator>; NUM_CLOCK_IDS],
+}
+
+impl Clocks {
+    /* Frequency of GCLK0 which drives the CPU and the synchronous peripheral buses */
+    pub fn gclk0(&self) -> Hertz {
+        self.gclk[0].unwrap()
+    }
+
+    pub fn generator(&self, gen: Generator) -> Option<Hertz> {
+        self.gclk[gen.id() as usize]
+    }
+
+    /* Frequency of the generic clock routed to peripheral channel `id` */
+    pub fn peripheral(&self, id: ClockId) -> Option<Hertz> {
+        self.routes[id.id() as usize].and_then(|gen| self.generator(gen))
+    }
+
+    /* Like `peripheral` but for drivers which can't work without the clock */
+    pub(crate) fn require(&self, id: ClockId) -> Result<Hertz, Error> {
+        self.peripheral(id).ok_or(Error::NotRouted(id))
+    }
+
+    /* Number of flash wait states required at the CPU frequency */
+    fn rws(&self) -> u8 {
+        let freq = self.gclk0().0;
+        RWS_MAX_FREQ
+            .iter()
+            .position(|max| freq <= *max)
+            .unwrap_or(RWS_MAX_FREQ.len()) as u8
+    }
+}
@@ -1,189 +1,34 @@
-use super::{Interrupt, TC0, EIC, GCLK, NVIC, NVMCTRL, PM, PORT, SYSCTRL, SYST};
+use super::delay::delay_ms;
+use super::{Interrupt, TC0, EIC, GCLK, NVIC, PM, PORT};
 
 extern crate cortex_m;
 
-use core::ptr;
 use cortex_m::interrupt;
-use cortex_m::peripheral::SystClkSource;
 
+/* Give a debugger about a second to attach before we reconfigure the chip */
 pub fn delay_init() {
-    for _ in 0..200_000 {
-        cortex_m::asm::nop();
-    }
+    delay_ms(1_000);
 }
 
-pub fn init_gpios() {
-    /* Enter critical section */
-    interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        let syst = SYST.borrow(cs);
-
-        /* Initialise PA0-PA24 to high */
-        port.outset
-            .write(|w| unsafe { w.outset().bits(0x1FF_FFFF) });
-
-        /* Set PA0-PA24 as output */
-        port.dir.write(|w| unsafe { w.dir().bits(0x1FF_FFFF) });
-
-        /* Set PA25 to input with pull-up and external interrupt enabled */
-        port.pincfg[25].modify(|_, w| w.inen().set_bit().pullen().set_bit().pmuxen().set_bit());
-
-        /* Set SysTick exception to lowest priority */
-        unsafe { ptr::write_volatile(0xE000_ED20 as *mut u32, 0xC000_0000) }
-
-        /* Initialise SysTick counter with a defined value */
-        unsafe { syst.cvr.write(1) };
-
-        /* Set source for SysTick counter, here full operating frequency (== 8MHz) */
-        syst.set_clock_source(SystClkSource::Core);
+pub fn init_gpios(port: &PORT) {
+    /* Initialise PA0-PA24 to high */
+    port.outset
+        .write(|w| unsafe { w.outset().bits(0x1FF_FFFF) });
 
-        /* Set reload value, i.e. timer delay (== 1/24s) */
-        syst.set_reload(4_000_000);
+    /* Set PA0-PA24 as output */
+    port.dir.write(|w| unsafe { w.dir().bits(0x1FF_FFFF) });
 
-        /* Start counter */
-        syst.enable_counter();
+    /* Select function A (EIC/EXTINT13) for PA25, it's the odd pin of PMUX12 */
+    port.pmux0_[12].modify(|_, w| w.pmuxo().a());
 
-        /* Start interrupt generation */
-        syst.enable_interrupt();
-    });
+    /* Set PA25 to input with pull-up and external interrupt enabled */
+    port.pincfg[25].modify(|_, w| w.inen().set_bit().pullen().set_bit().pmuxen().set_bit());
 }
 
-pub fn init_systick(reload: u32) {
-    /* Enter critical section */
-    interrupt::free(|cs| {
-        let syst = SYST.borrow(cs);
-
-        /* Set SysTick exception to lowest priority */
-        unsafe { ptr::write_volatile(0xE000_ED20 as *mut u32, 0xC000_0000) }
-
-        /* Initialise SysTick counter with a defined value */
-        unsafe { syst.cvr.write(1) };
-
-        /* Set source for SysTick counter, here full operating frequency (== 8MHz) */
-        syst.set_clock_source(SystClkSource::Core);
-
-        /* Set reload value, i.e. timer delay (== 1/24s) */
-        syst.set_reload(reload);
+pub fn setup_tc0(gclk: &GCLK, pm: &PM, tc0: &TC0, divider: u16) {
+    let tc0 = tc0.count16();
 
-        /* Start counter */
-        syst.enable_counter();
-
-        /* Start interrupt generation */
-        syst.enable_interrupt();
-    });
-}
-
-pub fn init_48_mhz_clock() {
     interrupt::free(|cs| {
-        let sysctrl = SYSCTRL.borrow(cs);
-        let gclk = GCLK.borrow(cs);
-        let nvmctrl = NVMCTRL.borrow(cs);
-
-        /* Set wait states */
-        nvmctrl.ctrlb.modify(|_, w| unsafe { w.rws().bits(1) });
-
-        /* Reset generic clock controller for good measure */
-        gclk.ctrl.modify(|_, w| w.swrst().bit(true));
-
-        /* Wait until it is reset */
-        while gclk.ctrl.read().swrst().bit_is_set() && gclk.status.read().syncbusy().bit_is_set() {}
-
-        /* Disable on-demand mode of 8 MHz oscillator */
-        sysctrl.osc8m.modify(|_, w| w.ondemand().clear_bit());
-
-        /* Set up divisor on clock generator 3 */
-        gclk.gendiv
-            .write(|w| unsafe { w.div().bits(64).id().bits(3) });
-
-        /* Set up 8 MHz clock as source for clock generator 3 */
-        gclk.genctrl
-            .write(|w| unsafe { w.id().bits(3).genen().set_bit().src().osc8m() });
-
-        /* Wait, again... */
-        while gclk.status.read().syncbusy().bit_is_set() {}
-
-        /* Set up clock generator 3 as input for DFLL */
-        gclk.clkctrl
-            .write(|w| unsafe { w.clken().set_bit().gen().bits(3).id().dfll48m() });
-
-        /* Wait, again... */
-        while gclk.status.read().syncbusy().bit_is_set() {}
-
-        /* Disable on-demand mode of DFLL */
-        sysctrl.dfllctrl.modify(|_, w| w.ondemand().clear_bit());
-
-        /* Wait, again... */
-        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
-
-        /* Set multiplicator for DFLL */
-        sysctrl
-            .dfllmul
-            .write(|w| unsafe { w.cstep().bits(1).fstep().bits(1).mul().bits(3072) });
-
-        /* Wait, again... */
-        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
-
-        /* Disable quick lock and enable open-loop mode */
-        sysctrl
-            .dfllctrl
-            .modify(|_, w| w.mode().set_bit().qldis().set_bit());
-
-        /* Wait, again... */
-        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
-
-        /* Enable DFLL fine and coarse lock and clean interrupt */
-        sysctrl.intflag.modify(|_, w| {
-            w.dflllckc()
-                .set_bit()
-                .dflllckf()
-                .set_bit()
-                .dfllrdy()
-                .set_bit()
-        });
-
-        /* Fire up DFLL */
-        sysctrl.dfllctrl.modify(|_, w| w.enable().set_bit());
-
-        /* Wait, again... */
-        while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
-
-        /* Wait for the DFLL to lock  */
-        while sysctrl.intflag.read().dflllckc().bit_is_clear()
-            && sysctrl.intflag.read().dflllckf().bit_is_clear()
-        {}
-
-        /* Wait, again... */
-        while sysctrl.intflag.read().dfllrdy().bit_is_clear() {}
-
-        /* Set up clock generator 0 (== CPU clock) without divisor */
-        gclk.gendiv
-            .write(|w| unsafe { w.div().bits(0).id().bits(0) });
-
-        /* Wait, again... */
-        while gclk.status.read().syncbusy().bit_is_set() {}
-
-        /* Set up clock generator 0 (== CPU clock) from DFLL source */
-        gclk.genctrl.write(|w| unsafe {
-            w.id()
-                .bits(0)
-                .genen()
-                .set_bit()
-                .src()
-                .dfll48m()
-                .idc()
-                .set_bit()
-        });
-
-        /* Wait, again... */
-        while gclk.status.read().syncbusy().bit_is_set() {}
-    });
-}
-
-pub fn setup_tc0(divider: u16) {
-    interrupt::free(|cs| {
-        let gclk = GCLK.borrow(cs);
-        let pm = PM.borrow(cs);
-        let tc0 = TC0.borrow(cs);
         let nvic = NVIC.borrow(cs);
 
         /* Setup CPU clock for TC0 and TC1 */
@@ -236,11 +81,8 @@ pub fn setup_tc0(divider: u16) {
 
 /* Setup EIC and PA25 to register external interrupts */
 
-pub fn setup_eic() {
+pub fn setup_eic(eic: &EIC, pm: &PM, gclk: &GCLK) {
     interrupt::free(|cs| {
-        let eic = EIC.borrow(cs);
-        let pm = PM.borrow(cs);
-        let gclk = GCLK.borrow(cs);
         let nvic = NVIC.borrow(cs);
 
         /* Enable clock for EIC */
@@ -291,16 +133,14 @@ pub fn setup_eic() {
     });
 }
 
+/* OUTSET and OUTCLR only touch the pins whose bits are written, so these are safe to use from
+ * interrupt handlers without owning PORT */
 pub fn pull_pins_high(bits: u32) {
-    cortex_m::interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        port.outset.modify(|_, w| unsafe { w.outset().bits(bits) });
-    });
+    let port = unsafe { &*PORT::ptr() };
+    port.outset.write(|w| unsafe { w.outset().bits(bits) });
 }
 
 pub fn pull_pins_low(bits: u32) {
-    cortex_m::interrupt::free(|cs| {
-        let port = PORT.borrow(cs);
-        port.outclr.modify(|_, w| unsafe { w.outclr().bits(bits) });
-    });
+    let port = unsafe { &*PORT::ptr() };
+    port.outclr.write(|w| unsafe { w.outclr().bits(bits) });
 }
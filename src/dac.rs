@@ -0,0 +1,270 @@
+use super::clock::{self, ClockId, Clocks};
+use super::evsys::{Channel, Edge, EventSystem, Generator, Path, User};
+use super::pmux::VoutPin;
+use super::power;
+use super::{Interrupt, DAC, NVIC, PM};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use nb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* A start event came before the next sample was in DATABUF */
+    Underrun,
+    /* The EVSYS channel of a stream has no generic clock */
+    Clock(clock::Error),
+}
+
+impl From<clock::Error> for Error {
+    fn from(error: clock::Error) -> Error {
+        Error::Clock(error)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Reference {
+    /* 1.0V internal reference */
+    Int1V,
+    /* Analog supply */
+    Avcc,
+    /* External reference on VREFA, i.e. PA3 */
+    VrefP,
+}
+
+/* Samples still to be loaded into DATABUF by `handle_interrupt` */
+struct Stream {
+    samples: &'static [u16],
+    position: usize,
+    repeat: bool,
+}
+
+static mut STREAM: Option<Stream> = None;
+static mut UNDERRUN: bool = false;
+
+/* 10 bit DAC, its output is always available to the ADC and AC internally */
+pub struct Dac {
+    dac: DAC,
+}
+
+impl Dac {
+    /* Take over the DAC, which needs a generic clock routed to it, and drive `vout` */
+    pub fn new<P: VoutPin>(
+        dac: DAC,
+        pm: &PM,
+        clocks: &Clocks,
+        reference: Reference,
+        _vout: &P,
+    ) -> Result<Dac, clock::Error> {
+        Dac::setup(dac, pm, clocks, reference, true)
+    }
+
+    /* Take over the DAC without driving the VOUT pin */
+    pub fn internal(
+        dac: DAC,
+        pm: &PM,
+        clocks: &Clocks,
+        reference: Reference,
+    ) -> Result<Dac, clock::Error> {
+        Dac::setup(dac, pm, clocks, reference, false)
+    }
+
+    fn setup(
+        dac: DAC,
+        pm: &PM,
+        clocks: &Clocks,
+        reference: Reference,
+        external: bool,
+    ) -> Result<Dac, clock::Error> {
+        clocks.require(ClockId::Dac)?;
+
+        /* Enable clock for the DAC */
+        pm.apbcmask.modify(|_, w| w.dac().set_bit());
+
+        /* Reset the DAC */
+        dac.ctrla.write(|w| w.swrst().set_bit());
+
+        /* And wait */
+        while dac.ctrla.read().swrst().bit_is_set() {}
+        while dac.status.read().syncbusy().bit_is_set() {}
+
+        dac.ctrlb.write(|w| {
+            let w = w.ioen().set_bit().eoen().bit(external);
+            match reference {
+                Reference::Int1V => w.refsel().int1v(),
+                Reference::Avcc => w.refsel().avcc(),
+                Reference::VrefP => w.refsel().vrefp(),
+            }
+        });
+
+        /* Enable */
+        dac.ctrla.modify(|_, w| w.enable().set_bit());
+
+        /* And wait */
+        while dac.status.read().syncbusy().bit_is_set() {}
+
+        Ok(Dac { dac })
+    }
+
+    /* Convert `value`, only the lower 10 bits are used */
+    pub fn write(&mut self, value: u16) {
+        self.dac.data.write(|w| unsafe { w.data().bits(value) });
+
+        /* And wait */
+        while self.dac.status.read().syncbusy().bit_is_set() {}
+    }
+
+    /* Play `samples` with each event of `trigger`, e.g. `Generator::TcOvf(n)` of a timer with its
+     * overflow event output enabled, on EVSYS `channel`. `handle_interrupt` keeps DATABUF loaded
+     * with the next sample. Without samples there's nothing to play and the stream is done right
+     * away */
+    pub fn stream(
+        &mut self,
+        evsys: &mut EventSystem,
+        clocks: &Clocks,
+        channel: Channel,
+        trigger: Generator,
+        samples: &'static [u16],
+        repeat: bool,
+    ) -> Result<(), Error> {
+        if samples.is_empty() {
+            self.stop_stream(evsys);
+            interrupt::free(|_| unsafe { UNDERRUN = false });
+            return Ok(());
+        }
+
+        let dac = &self.dac;
+
+        interrupt::free(move |_| unsafe {
+            STREAM = Some(Stream {
+                samples,
+                position: 0,
+                repeat,
+            });
+            UNDERRUN = false;
+        });
+
+        evsys.connect(
+            channel,
+            trigger,
+            User::DacStart,
+            Path::Asynchronous,
+            Edge::None,
+            clocks,
+        )?;
+
+        /* Move DATABUF into DATA on each event */
+        dac.evctrl.write(|w| w.startei().set_bit());
+
+        interrupt::free(|cs| {
+            let nvic = NVIC.borrow(cs);
+
+            /* EMPTY is already set, so the handler loads the first sample right away */
+            dac.intflag.write(|w| w.underrun().set_bit());
+            dac.intenset
+                .write(|w| w.empty().set_bit().underrun().set_bit());
+
+            /* Enable DAC IRQs */
+            nvic.enable(Interrupt::DAC);
+        });
+
+        Ok(())
+    }
+
+    /* Whether all samples of a stream have been played */
+    pub fn stream_done(&mut self) -> nb::Result<(), Error> {
+        let underrun = interrupt::free(|_| unsafe {
+            let underrun = UNDERRUN;
+            UNDERRUN = false;
+            underrun
+        });
+
+        if underrun {
+            return Err(nb::Error::Other(Error::Underrun));
+        }
+
+        if unsafe { STREAM.is_some() } {
+            Err(nb::Error::WouldBlock)
+        } else {
+            Ok(())
+        }
+    }
+
+    /* Stop a stream, the current sample stays on the output */
+    pub fn stop_stream(&mut self, evsys: &mut EventSystem) {
+        let dac = &self.dac;
+
+        interrupt::free(|_| unsafe {
+            dac.intenclr
+                .write(|w| w.empty().set_bit().underrun().set_bit());
+            STREAM = None;
+        });
+
+        dac.evctrl.write(|w| w.startei().clear_bit());
+        evsys.disconnect(User::DacStart);
+    }
+
+    /* To be called from the interrupt handler of the DAC */
+    pub fn handle_interrupt() {
+        let dac = unsafe { &*DAC::ptr() };
+        let flags = dac.intflag.read();
+
+        if flags.underrun().bit_is_set() {
+            dac.intflag.write(|w| w.underrun().set_bit());
+            unsafe { UNDERRUN = true };
+        }
+
+        if flags.empty().bit_is_set() && dac.intenset.read().empty().bit_is_set() {
+            let next = match unsafe { STREAM.as_mut() } {
+                Some(stream) => {
+                    if stream.position == stream.samples.len() && stream.repeat {
+                        stream.position = 0;
+                    }
+
+                    let sample = stream.samples.get(stream.position).cloned();
+                    stream.position += 1;
+                    sample
+                }
+                None => None,
+            };
+
+            match next {
+                Some(sample) => {
+                    dac.databuf.write(|w| unsafe { w.databuf().bits(sample) });
+
+                    /* And wait */
+                    while dac.status.read().syncbusy().bit_is_set() {}
+                }
+                None => {
+                    /* The last sample has moved to DATA, ignore further events */
+                    dac.intenclr
+                        .write(|w| w.empty().set_bit().underrun().set_bit());
+                    dac.evctrl.write(|w| w.startei().clear_bit());
+                    unsafe { STREAM = None };
+                }
+            }
+        }
+    }
+
+    /* Keep the DAC running in standby, which needs its generic clock to run in standby as well.
+     * The DAC is briefly disabled */
+    pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+        power::dac_run_in_standby(&self.dac, run_in_standby);
+    }
+
+    /* Hand back the DAC after disabling it */
+    pub fn free(self, pm: &PM) -> DAC {
+        self.dac.intenclr.write(|w| unsafe { w.bits(0xFF) });
+        unsafe { STREAM = None };
+
+        self.dac.ctrla.modify(|_, w| w.enable().clear_bit());
+
+        /* And wait */
+        while self.dac.status.read().syncbusy().bit_is_set() {}
+
+        pm.apbcmask.modify(|_, w| w.dac().clear_bit());
+
+        self.dac
+    }
+}
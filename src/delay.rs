@@ -0,0 +1,186 @@
+use super::time::Hertz;
+use super::SYST;
+
+use core::ptr;
+use cortex_m::peripheral::SystClkSource;
+use embedded_hal::blocking::delay::{DelayMs, DelayUs};
+
+/* Largest value the 24 bit SysTick reload register can hold */
+const SYST_MAX_RELOAD: u32 = 0x00FF_FFFF;
+
+/* Shortest SysTick period in core clock cycles, so the exception handler still gets to run */
+const SYST_MIN_CYCLES: u64 = 256;
+
+/* Core clock frequency, updated by `ClockConfig::freeze`. Until then it's the reset clock of
+ * 1 MHz, i.e. the 8 MHz OSC8M with its reset prescaler of 8, so the delays and SysTick periods
+ * are only right for a clock tree set up through `ClockConfig` */
+static mut CORE_FREQ: u32 = 1_000_000;
+
+/* Number of SysTick exceptions making up one period of the periodic SysTick and how many of
+ * them are still left until the current period is over */
+static mut SYSTICK_PRESCALER: u32 = 1;
+static mut SYSTICK_COUNTDOWN: u32 = 1;
+
+pub(crate) fn set_core_frequency(freq: Hertz) {
+    unsafe { CORE_FREQ = freq.0 };
+}
+
+/* Core clock frequency the delays and SysTick periods are based on, 1 MHz before
+ * `ClockConfig::freeze` */
+pub fn core_frequency() -> Hertz {
+    Hertz(unsafe { CORE_FREQ })
+}
+
+/* Busy wait for the given number of core clock cycles using the SysTick counter. If SysTick is
+ * already running as periodic timer we just follow its count, so it's safe to delay while the
+ * SysTick exception keeps firing */
+fn delay_cycles(cycles: u64) {
+    /* We only ever read the counter of a running SysTick so we don't need a critical section */
+    let syst = unsafe { &*SYST.get() };
+
+    let running = syst.is_counter_enabled();
+    if !running {
+        syst.set_clock_source(SystClkSource::Core);
+        syst.set_reload(SYST_MAX_RELOAD);
+        syst.clear_current();
+        syst.enable_counter();
+    }
+
+    /* The counter counts down from the reload value to 0 and then wraps */
+    let reload = syst.get_reload();
+    let mut last = syst.get_current();
+    let mut elapsed: u64 = 0;
+    while elapsed < cycles {
+        let now = syst.get_current();
+        elapsed += if now <= last {
+            last - now
+        } else {
+            last + reload + 1 - now
+        } as u64;
+        last = now;
+    }
+
+    if !running {
+        syst.disable_counter();
+    }
+}
+
+pub fn delay_us(us: u32) {
+    delay_cycles(unsafe { CORE_FREQ } as u64 * us as u64 / 1_000_000);
+}
+
+pub fn delay_ms(ms: u32) {
+    delay_cycles(unsafe { CORE_FREQ } as u64 * ms as u64 / 1_000);
+}
+
+/* Blocking delay for use with embedded-hal drivers */
+pub struct Delay;
+
+impl Delay {
+    pub fn new() -> Delay {
+        Delay
+    }
+}
+
+impl DelayMs<u32> for Delay {
+    fn delay_ms(&mut self, ms: u32) {
+        delay_ms(ms);
+    }
+}
+
+impl DelayMs<u16> for Delay {
+    fn delay_ms(&mut self, ms: u16) {
+        delay_ms(ms as u32);
+    }
+}
+
+impl DelayMs<u8> for Delay {
+    fn delay_ms(&mut self, ms: u8) {
+        delay_ms(ms as u32);
+    }
+}
+
+impl DelayUs<u32> for Delay {
+    fn delay_us(&mut self, us: u32) {
+        delay_us(us);
+    }
+}
+
+impl DelayUs<u16> for Delay {
+    fn delay_us(&mut self, us: u16) {
+        delay_us(us as u32);
+    }
+}
+
+impl DelayUs<u8> for Delay {
+    fn delay_us(&mut self, us: u8) {
+        delay_us(us as u32);
+    }
+}
+
+/* Fire the SysTick exception `freq` times per second, 0 Hz is taken as 1 Hz and periods are
+ * at least 256 core clock cycles */
+pub fn systick_periodic<T: Into<Hertz>>(freq: T) {
+    let freq = (freq.into().0 as u64).max(1);
+    systick_start((unsafe { CORE_FREQ } as u64 + freq / 2) / freq);
+}
+
+/* Fire the SysTick exception every `period_us` microseconds. Periods shorter than 256 core clock
+ * cycles, including 0, are stretched to that */
+pub fn systick_periodic_us(period_us: u32) {
+    systick_start(unsafe { CORE_FREQ } as u64 * period_us as u64 / 1_000_000);
+}
+
+fn systick_start(cycles: u64) {
+    let cycles = cycles.max(SYST_MIN_CYCLES);
+
+    /* Periods longer than the 24 bit counter can count are split into several equally long
+     * SysTick periods which are counted in software by `systick_elapsed` */
+    let prescaler = ((cycles + SYST_MAX_RELOAD as u64) / (SYST_MAX_RELOAD as u64 + 1)).max(1);
+    let reload = cycles / prescaler - 1;
+
+    /* Enter critical section */
+    cortex_m::interrupt::free(|cs| {
+        let syst = SYST.borrow(cs);
+
+        unsafe {
+            SYSTICK_PRESCALER = prescaler as u32;
+            SYSTICK_COUNTDOWN = prescaler as u32;
+        }
+
+        /* Set SysTick exception to lowest priority */
+        unsafe { ptr::write_volatile(0xE000_ED20 as *mut u32, 0xC000_0000) }
+
+        /* Stop counter while we change the reload value */
+        syst.disable_counter();
+
+        /* Set source for SysTick counter, here full core frequency */
+        syst.set_clock_source(SystClkSource::Core);
+
+        /* Set reload value, i.e. timer delay */
+        syst.set_reload(reload as u32);
+
+        /* Initialise SysTick counter with a defined value */
+        syst.clear_current();
+
+        /* Start counter */
+        syst.enable_counter();
+
+        /* Start interrupt generation */
+        syst.enable_interrupt();
+    });
+}
+
+/* To be called from the SysTick exception handler, returns true once per period set up by
+ * `systick_periodic` or `systick_periodic_us` */
+pub fn systick_elapsed() -> bool {
+    unsafe {
+        SYSTICK_COUNTDOWN -= 1;
+        if SYSTICK_COUNTDOWN == 0 {
+            SYSTICK_COUNTDOWN = SYSTICK_PRESCALER;
+            true
+        } else {
+            false
+        }
+    }
+}
@@ -0,0 +1,206 @@
+use super::clock::{ClockId, Clocks, Error};
+use super::pmux::{ExtIntPin, NmiPin};
+use super::power::{self, WakeSource};
+use super::{Interrupt, EIC, NVIC, PM};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+
+const NUM_LINES: usize = 16;
+
+/* Callbacks for EXTINT0-15 and the NMI, run by `handle_interrupt` and `handle_nmi` */
+static mut CALLBACKS: [Option<fn()>; NUM_LINES] = [None; NUM_LINES];
+static mut NMI_CALLBACK: Option<fn()> = None;
+
+/* Input sense configuration, numbered like CONFIG.SENSEn and NMICTRL.NMISENSE */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Sense {
+    None = 0,
+    Rise,
+    Fall,
+    Both,
+    High,
+    Low,
+}
+
+pub struct Eic {
+    eic: EIC,
+}
+
+impl Eic {
+    /* Take over the EIC, which needs a generic clock routed to `ClockId::Eic` for edge detection
+     * and filtering */
+    pub fn new(eic: EIC, pm: &PM, clocks: &Clocks) -> Result<Eic, Error> {
+        clocks.require(ClockId::Eic)?;
+
+        /* Enable clock for EIC */
+        pm.apbamask.modify(|_, w| w.eic().set_bit());
+
+        /* Reset the EIC */
+        eic.ctrl.write(|w| w.swrst().set_bit());
+
+        /* Wait for the reset to finish */
+        while eic.ctrl.read().swrst().bit_is_set() {}
+        while eic.status.read().syncbusy().bit_is_set() {}
+
+        /* Enable EIC */
+        eic.ctrl.write(|w| w.enable().set_bit());
+
+        /* And wait */
+        while eic.status.read().syncbusy().bit_is_set() {}
+
+        interrupt::free(|cs| {
+            let nvic = NVIC.borrow(cs);
+
+            /* Enable EIC IRQs and clear any pending ones */
+            nvic.clear_pending(Interrupt::EIC);
+            nvic.enable(Interrupt::EIC);
+        });
+
+        Ok(Eic { eic })
+    }
+
+    /* CONFIG and EVCTRL can only be written while the EIC is disabled */
+    fn disabled<F: FnOnce(&EIC)>(&mut self, f: F) {
+        self.eic.ctrl.write(|w| w.enable().clear_bit());
+        while self.eic.status.read().syncbusy().bit_is_set() {}
+
+        f(&self.eic);
+
+        self.eic.ctrl.write(|w| w.enable().set_bit());
+        while self.eic.status.read().syncbusy().bit_is_set() {}
+    }
+
+    /* Set what the EXTINT line of `pin` detects and whether its input is filtered */
+    pub fn configure<P: ExtIntPin>(&mut self, _pin: &P, sense: Sense, filter: bool) {
+        let line = P::EXTINT as usize;
+        let shift = (line & 7) * 4;
+        let value = (sense as u32) | ((filter as u32) << 3);
+
+        self.disabled(|eic| {
+            eic.config[line >> 3]
+                .modify(|r, w| unsafe { w.bits((r.bits() & !(0xF << shift)) | (value << shift)) });
+        });
+    }
+
+    /* Wake the device from sleep on the EXTINT line of `pin` */
+    pub fn set_wakeup<P: ExtIntPin>(&mut self, _pin: &P, enable: bool) {
+        let mask = 1 << P::EXTINT;
+        self.eic.wakeup.modify(|r, w| unsafe {
+            w.bits(if enable {
+                r.bits() | mask
+            } else {
+                r.bits() & !mask
+            })
+        });
+    }
+
+    /* Generate an event on the EXTINT line of `pin` for use with the event system */
+    pub fn set_event_output<P: ExtIntPin>(&mut self, _pin: &P, enable: bool) {
+        let mask = 1 << P::EXTINT;
+        self.disabled(|eic| {
+            eic.evctrl.modify(|r, w| unsafe {
+                w.bits(if enable {
+                    r.bits() | mask
+                } else {
+                    r.bits() & !mask
+                })
+            });
+        });
+    }
+
+    /* Call `callback` from `handle_interrupt` whenever the EXTINT line of `pin` triggers */
+    pub fn listen<P: ExtIntPin>(&mut self, _pin: &P, callback: fn()) {
+        let line = P::EXTINT;
+
+        /* Make sure the handler doesn't run while we swap the callback */
+        self.eic.intenclr.write(|w| unsafe { w.bits(1 << line) });
+        unsafe { CALLBACKS[line as usize] = Some(callback) };
+
+        /* Clear any stale interrupt and enable the line */
+        self.eic.intflag.write(|w| unsafe { w.bits(1 << line) });
+        self.eic.intenset.write(|w| unsafe { w.bits(1 << line) });
+    }
+
+    pub fn unlisten<P: ExtIntPin>(&mut self, _pin: &P) {
+        let line = P::EXTINT;
+
+        self.eic.intenclr.write(|w| unsafe { w.bits(1 << line) });
+        unsafe { CALLBACKS[line as usize] = None };
+    }
+
+    /* Set up the non-maskable interrupt on `pin`, `callback` is run from `handle_nmi` */
+    pub fn configure_nmi<P: NmiPin>(
+        &mut self,
+        _pin: &P,
+        sense: Sense,
+        filter: bool,
+        callback: fn(),
+    ) {
+        unsafe { NMI_CALLBACK = Some(callback) };
+
+        /* Clear any stale NMI */
+        self.eic.nmiflag.write(|w| w.nmi().set_bit());
+
+        self.eic
+            .nmictrl
+            .write(|w| unsafe { w.nmisense().bits(sense as u8).nmifilten().bit(filter) });
+    }
+
+    /* EXTINT lines with both their wake-up and their interrupt enabled, none without bus clock or
+     * with the EIC IRQ disabled */
+    pub fn armed_lines(&self, pm: &PM) -> u16 {
+        if power::bus_clock_enabled(pm, &self.eic) && power::nvic_enabled(Interrupt::EIC) {
+            (self.eic.wakeup.read().bits() & self.eic.intenset.read().bits()) as u16
+        } else {
+            0
+        }
+    }
+
+    /* Hand back the EIC after disabling it */
+    pub fn free(self, pm: &PM) -> EIC {
+        self.eic.ctrl.write(|w| w.enable().clear_bit());
+        while self.eic.status.read().syncbusy().bit_is_set() {}
+
+        pm.apbamask.modify(|_, w| w.eic().clear_bit());
+
+        self.eic
+    }
+}
+
+impl WakeSource for Eic {
+    fn is_armed(&self, pm: &PM) -> bool {
+        self.armed_lines(pm) != 0
+    }
+}
+
+/* To be called from the EIC interrupt handler: clears the flag of every triggered line and runs
+ * its callback */
+pub fn handle_interrupt() {
+    let eic = unsafe { &*EIC::ptr() };
+
+    let pending = eic.intflag.read().bits() & eic.intenset.read().bits();
+    eic.intflag.write(|w| unsafe { w.bits(pending) });
+
+    for line in 0..NUM_LINES {
+        if pending & (1 << line) != 0 {
+            if let Some(callback) = unsafe { CALLBACKS[line] } {
+                callback();
+            }
+        }
+    }
+}
+
+/* To be called from the NMI exception handler */
+pub fn handle_nmi() {
+    let eic = unsafe { &*EIC::ptr() };
+
+    if eic.nmiflag.read().nmi().bit_is_set() {
+        eic.nmiflag.write(|w| w.nmi().set_bit());
+
+        if let Some(callback) = unsafe { NMI_CALLBACK } {
+            callback();
+        }
+    }
+}
@@ -0,0 +1,169 @@
+use super::clock::{ClockId, Clocks, Error};
+use super::{EVSYS, PM};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Ch0 = 0,
+    Ch1,
+    Ch2,
+    Ch3,
+    Ch4,
+    Ch5,
+    Ch6,
+    Ch7,
+}
+
+/* Event generators, numbered like CHANNEL.EVGEN */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Generator {
+    RtcCmp(u8),
+    RtcOvf,
+    RtcPer(u8),
+    ExtInt(u8),
+    TcOvf(u8),
+    TcMc(u8, u8),
+    AdcResrdy,
+    AdcWinmon,
+    AcComp(u8),
+    AcWin0,
+    DacEmpty,
+}
+
+impl Generator {
+    pub fn id(self) -> u8 {
+        match self {
+            Generator::RtcCmp(n) => 0x01 + n,
+            Generator::RtcOvf => 0x03,
+            Generator::RtcPer(n) => 0x04 + n,
+            Generator::ExtInt(n) => 0x0C + n,
+            Generator::TcOvf(tc) => 0x1C + tc * 3,
+            Generator::TcMc(tc, n) => 0x1D + tc * 3 + n,
+            Generator::AdcResrdy => 0x34,
+            Generator::AdcWinmon => 0x35,
+            Generator::AcComp(n) => 0x36 + n,
+            Generator::AcWin0 => 0x38,
+            Generator::DacEmpty => 0x39,
+        }
+    }
+}
+
+/* Event users, numbered like USER.USER */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum User {
+    Tc(u8),
+    AdcStart,
+    AdcSync,
+    AcSoc(u8),
+    DacStart,
+}
+
+impl User {
+    pub fn id(self) -> u8 {
+        match self {
+            User::Tc(tc) => tc,
+            User::AdcStart => 0x08,
+            User::AdcSync => 0x09,
+            User::AcSoc(n) => 0x0A + n,
+            User::DacStart => 0x0C,
+        }
+    }
+}
+
+/* How events travel from the generator to the user. Only the synchronous and resynchronized
+ * paths need the generic clock of the channel, and only they support edge detection */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Path {
+    Synchronous = 0,
+    Resynchronized,
+    Asynchronous,
+}
+
+/* Edge of the generator signal that makes an event on the synchronous and resynchronized paths */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Edge {
+    None = 0,
+    Rising,
+    Falling,
+    Both,
+}
+
+const CHANNEL_CLOCKS: [ClockId; 8] = [
+    ClockId::EvsysChannel0,
+    ClockId::EvsysChannel1,
+    ClockId::EvsysChannel2,
+    ClockId::EvsysChannel3,
+    ClockId::EvsysChannel4,
+    ClockId::EvsysChannel5,
+    ClockId::EvsysChannel6,
+    ClockId::EvsysChannel7,
+];
+
+pub struct EventSystem {
+    evsys: EVSYS,
+}
+
+impl EventSystem {
+    pub fn new(evsys: EVSYS, pm: &PM) -> EventSystem {
+        /* Enable clock for EVSYS */
+        pm.apbcmask.modify(|_, w| w.evsys().set_bit());
+
+        /* Reset the EVSYS, it isn't synchronised so there's nothing to wait for */
+        evsys.ctrl.write(|w| w.swrst().set_bit());
+
+        EventSystem { evsys }
+    }
+
+    /* Route events of `generator` through `channel` to `user` */
+    pub fn connect(
+        &mut self,
+        channel: Channel,
+        generator: Generator,
+        user: User,
+        path: Path,
+        edge: Edge,
+        clocks: &Clocks,
+    ) -> Result<(), Error> {
+        let channel = channel as u8;
+
+        if path != Path::Asynchronous {
+            clocks.require(CHANNEL_CLOCKS[channel as usize])?;
+        }
+
+        /* Connect the user first so no event gets lost, USER.CHANNEL is the channel number + 1 */
+        self.evsys
+            .user
+            .write(|w| unsafe { w.user().bits(user.id()).channel().bits(channel + 1) });
+
+        self.evsys.channel.write(|w| unsafe {
+            w.channel()
+                .bits(channel)
+                .evgen()
+                .bits(generator.id())
+                .path()
+                .bits(path as u8)
+                .edgsel()
+                .bits(edge as u8)
+        });
+
+        /* Wait for the user to get ready on clocked paths */
+        if path != Path::Asynchronous {
+            while self.evsys.chstatus.read().bits() & (1 << channel) == 0 {}
+        }
+
+        Ok(())
+    }
+
+    /* Detach `user` from whatever channel it listens to */
+    pub fn disconnect(&mut self, user: User) {
+        self.evsys
+            .user
+            .write(|w| unsafe { w.user().bits(user.id()).channel().bits(0) });
+    }
+
+    /* Hand back the EVSYS after disabling its clock */
+    pub fn free(self, pm: &PM) -> EVSYS {
+        pm.apbcmask.modify(|_, w| w.evsys().clear_bit());
+
+        self.evsys
+    }
+}
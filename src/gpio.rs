@@ -0,0 +1,313 @@
+use super::{port, PORT};
+
+use core::marker::PhantomData;
+use embedded_hal::digital::v2::{InputPin, OutputPin, StatefulOutputPin, ToggleableOutputPin};
+use void::Void;
+
+/* Split PORT into individually owned pins which carry their configuration in their type */
+pub trait GpioExt {
+    type Parts;
+
+    fn split(self) -> Self::Parts;
+}
+
+/* Pin state after reset: input buffer, pull and output driver disabled */
+pub struct Disabled;
+
+pub struct Input<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+pub struct Floating;
+pub struct PullUp;
+pub struct PullDown;
+
+pub struct Output<MODE> {
+    _mode: PhantomData<MODE>,
+}
+
+pub struct PushPull;
+pub struct StrongDrive;
+
+/* Peripheral functions A-H as selected via PMUX */
+pub struct PfA;
+pub struct PfB;
+pub struct PfC;
+pub struct PfD;
+pub struct PfE;
+pub struct PfF;
+pub struct PfG;
+pub struct PfH;
+
+pub trait PeripheralFunction {
+    const PMUX: u8;
+}
+
+impl PeripheralFunction for PfA {
+    const PMUX: u8 = 0;
+}
+
+impl PeripheralFunction for PfB {
+    const PMUX: u8 = 1;
+}
+
+impl PeripheralFunction for PfC {
+    const PMUX: u8 = 2;
+}
+
+impl PeripheralFunction for PfD {
+    const PMUX: u8 = 3;
+}
+
+impl PeripheralFunction for PfE {
+    const PMUX: u8 = 4;
+}
+
+impl PeripheralFunction for PfF {
+    const PMUX: u8 = 5;
+}
+
+impl PeripheralFunction for PfG {
+    const PMUX: u8 = 6;
+}
+
+impl PeripheralFunction for PfH {
+    const PMUX: u8 = 7;
+}
+
+/* Token for the registers shared between pins (DIR, PINCFG and PMUX), required to change the
+ * configuration of a pin */
+pub struct Port {
+    _0: (),
+}
+
+impl Port {
+    fn port(&mut self) -> &port::RegisterBlock {
+        unsafe { &*PORT::ptr() }
+    }
+
+    fn into_disabled(&mut self, pin: usize) {
+        let port = self.port();
+        port.dirclr.write(|w| unsafe { w.dirclr().bits(1 << pin) });
+        port.pincfg[pin].reset();
+    }
+
+    fn into_input(&mut self, pin: usize, pull: Option<bool>) {
+        let port = self.port();
+
+        /* Make pin an input */
+        port.dirclr.write(|w| unsafe { w.dirclr().bits(1 << pin) });
+
+        /* For inputs OUT selects between pull-up and pull-down */
+        match pull {
+            Some(true) => port.outset.write(|w| unsafe { w.outset().bits(1 << pin) }),
+            Some(false) => port.outclr.write(|w| unsafe { w.outclr().bits(1 << pin) }),
+            None => {}
+        }
+
+        port.pincfg[pin].write(|w| w.inen().set_bit().pullen().bit(pull.is_some()));
+    }
+
+    fn into_output(&mut self, pin: usize, strong: bool) {
+        let port = self.port();
+
+        /* Enable the input buffer too so the pin level can still be read back */
+        port.pincfg[pin].write(|w| w.inen().set_bit().drvstr().bit(strong));
+
+        /* Make pin an output */
+        port.dirset.write(|w| unsafe { w.dirset().bits(1 << pin) });
+    }
+
+    fn into_function(&mut self, pin: usize, function: u8) {
+        let port = self.port();
+
+        /* Two pins share one PMUX register, even ones use the lower, odd ones the upper nibble */
+        if pin & 1 == 0 {
+            port.pmux0_[pin >> 1].modify(|_, w| unsafe { w.pmuxe().bits(function) });
+        } else {
+            port.pmux0_[pin >> 1].modify(|_, w| unsafe { w.pmuxo().bits(function) });
+        }
+
+        /* Hand the pin to the peripheral, pull and input settings stay as they are */
+        port.pincfg[pin].modify(|_, w| w.pmuxen().set_bit());
+    }
+}
+
+macro_rules! gpio {
+    ($($PAi:ident: ($pai:ident, $i:expr),)+) => {
+        pub struct Parts {
+            pub port: Port,
+            $(
+                pub $pai: $PAi<Disabled>,
+            )+
+        }
+
+        impl GpioExt for PORT {
+            type Parts = Parts;
+
+            fn split(self) -> Parts {
+                Parts {
+                    port: Port { _0: () },
+                    $(
+                        $pai: $PAi { _mode: PhantomData },
+                    )+
+                }
+            }
+        }
+
+        $(
+            pub struct $PAi<MODE> {
+                _mode: PhantomData<MODE>,
+            }
+
+            impl<MODE> $PAi<MODE> {
+                pub fn into_disabled(self, port: &mut Port) -> $PAi<Disabled> {
+                    port.into_disabled($i);
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_floating_input(self, port: &mut Port) -> $PAi<Input<Floating>> {
+                    port.into_input($i, None);
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_pull_up_input(self, port: &mut Port) -> $PAi<Input<PullUp>> {
+                    port.into_input($i, Some(true));
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_pull_down_input(self, port: &mut Port) -> $PAi<Input<PullDown>> {
+                    port.into_input($i, Some(false));
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_push_pull_output(self, port: &mut Port) -> $PAi<Output<PushPull>> {
+                    port.into_output($i, false);
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_strong_drive_output(
+                    self,
+                    port: &mut Port,
+                ) -> $PAi<Output<StrongDrive>> {
+                    port.into_output($i, true);
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_function<F: PeripheralFunction>(self, port: &mut Port) -> $PAi<F> {
+                    port.into_function($i, F::PMUX);
+                    $PAi { _mode: PhantomData }
+                }
+
+                pub fn into_function_a(self, port: &mut Port) -> $PAi<PfA> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_b(self, port: &mut Port) -> $PAi<PfB> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_c(self, port: &mut Port) -> $PAi<PfC> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_d(self, port: &mut Port) -> $PAi<PfD> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_e(self, port: &mut Port) -> $PAi<PfE> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_f(self, port: &mut Port) -> $PAi<PfF> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_g(self, port: &mut Port) -> $PAi<PfG> {
+                    self.into_function(port)
+                }
+
+                pub fn into_function_h(self, port: &mut Port) -> $PAi<PfH> {
+                    self.into_function(port)
+                }
+            }
+
+            impl<MODE> InputPin for $PAi<Input<MODE>> {
+                type Error = Void;
+
+                fn is_high(&self) -> Result<bool, Void> {
+                    Ok(unsafe { (*PORT::ptr()).in_.read().bits() & (1 << $i) != 0 })
+                }
+
+                fn is_low(&self) -> Result<bool, Void> {
+                    self.is_high().map(|high| !high)
+                }
+            }
+
+            /* OUTSET, OUTCLR and OUTTGL only touch our own pin so no token is needed */
+            impl<MODE> OutputPin for $PAi<Output<MODE>> {
+                type Error = Void;
+
+                fn set_high(&mut self) -> Result<(), Void> {
+                    unsafe { (*PORT::ptr()).outset.write(|w| w.outset().bits(1 << $i)) };
+                    Ok(())
+                }
+
+                fn set_low(&mut self) -> Result<(), Void> {
+                    unsafe { (*PORT::ptr()).outclr.write(|w| w.outclr().bits(1 << $i)) };
+                    Ok(())
+                }
+            }
+
+            impl<MODE> StatefulOutputPin for $PAi<Output<MODE>> {
+                fn is_set_high(&self) -> Result<bool, Void> {
+                    Ok(unsafe { (*PORT::ptr()).out.read().bits() & (1 << $i) != 0 })
+                }
+
+                fn is_set_low(&self) -> Result<bool, Void> {
+                    self.is_set_high().map(|high| !high)
+                }
+            }
+
+            impl<MODE> ToggleableOutputPin for $PAi<Output<MODE>> {
+                type Error = Void;
+
+                fn toggle(&mut self) -> Result<(), Void> {
+                    unsafe { (*PORT::ptr()).outtgl.write(|w| w.outtgl().bits(1 << $i)) };
+                    Ok(())
+                }
+            }
+        )+
+    };
+}
+
+/* Only the pins bonded out on the 32 pin E package, PA12, PA13, PA20, PA21, PA26 and PA29 are
+ * missing */
+gpio!(
+    Pa0: (pa0, 0),
+    Pa1: (pa1, 1),
+    Pa2: (pa2, 2),
+    Pa3: (pa3, 3),
+    Pa4: (pa4, 4),
+    Pa5: (pa5, 5),
+    Pa6: (pa6, 6),
+    Pa7: (pa7, 7),
+    Pa8: (pa8, 8),
+    Pa9: (pa9, 9),
+    Pa10: (pa10, 10),
+    Pa11: (pa11, 11),
+    Pa14: (pa14, 14),
+    Pa15: (pa15, 15),
+    Pa16: (pa16, 16),
+    Pa17: (pa17, 17),
+    Pa18: (pa18, 18),
+    Pa19: (pa19, 19),
+    Pa22: (pa22, 22),
+    Pa23: (pa23, 23),
+    Pa24: (pa24, 24),
+    Pa25: (pa25, 25),
+    Pa27: (pa27, 27),
+    Pa28: (pa28, 28),
+    Pa30: (pa30, 30),
+    Pa31: (pa31, 31),
+);
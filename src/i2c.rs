@@ -0,0 +1,653 @@
+use super::clock::{self, ClockId, Clocks};
+use super::pmux::{Pad0, Pad1, SercomPad};
+use super::power::{self, WakeSource};
+use super::{Interrupt, NVIC, PM, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use embedded_hal::blocking::i2c::{Read, Write, WriteRead};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* The slave didn't acknowledge its address or a data byte */
+    Nack,
+    /* Another master won the bus */
+    ArbitrationLost,
+    /* A start or stop condition turned up in the middle of a byte */
+    BusError,
+    /* SCL was held low for more than 25ms */
+    SclLowTimeout,
+}
+
+/* Bus state as tracked by the master in STATUS.BUSSTATE */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BusState {
+    Unknown = 0,
+    Idle,
+    Owner,
+    Busy,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Speed {
+    /* 100kHz */
+    Standard,
+    /* 400kHz */
+    Fast,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Address {
+    SevenBit(u8),
+    TenBit(u16),
+}
+
+/* CTRLB.CMD values of the master */
+const CMD_READ: u8 = 2;
+const CMD_STOP: u8 = 3;
+
+/* CTRLB.CMD values of the slave */
+const CMD_WAIT_START: u8 = 2;
+const CMD_CONTINUE: u8 = 3;
+
+/* CTRLB.AMODE values of the slave */
+const AMODE_MASK: u8 = 0;
+const AMODE_2ADDR: u8 = 1;
+
+/* Baud rate setting ignoring the rise time: BAUD = clock / (2 * scl) - 5, rounded so we never run
+ * faster than asked for. `None` if the clock is too slow for `speed` */
+fn baud_value(clock: u32, speed: Speed) -> Option<u8> {
+    let scl = match speed {
+        Speed::Standard => 100_000,
+        Speed::Fast => 400_000,
+    };
+    let divider = (clock + 2 * scl - 1) / (2 * scl);
+    divider.checked_sub(5).map(|baud| baud.min(0xFF) as u8)
+}
+
+/* First address byte of a 10 bit address, 11110 followed by the two high address bits */
+fn ten_bit_header(address: u16) -> u8 {
+    0xF0 | ((address >> 7) as u8 & 0x06)
+}
+
+/* I2C master with SDA on PAD0 and SCL on PAD1 */
+pub struct I2c<SERCOM, SDA, SCL> {
+    sercom: SERCOM,
+    sda: SDA,
+    scl: SCL,
+}
+
+macro_rules! i2c {
+    ($($SERCOM:ident: ($sercom:ident, $id:ident),)+) => {
+        $(
+            impl<SDA, SCL> I2c<$SERCOM, SDA, SCL>
+            where
+                SDA: SercomPad<$SERCOM, Pad = Pad0>,
+                SCL: SercomPad<$SERCOM, Pad = Pad1>,
+            {
+                /* Take over the SERCOM, which needs a generic clock routed to its core channel.
+                 * The SCL low timeout is only detected if the slow SERCOM clock is routed too */
+                pub fn $sercom(
+                    sercom: $SERCOM,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    speed: Speed,
+                    sda: SDA,
+                    scl: SCL,
+                ) -> Result<I2c<$SERCOM, SDA, SCL>, clock::Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+                    let baud = baud_value(clock.0, speed)
+                        .ok_or(clock::Error::FrequencyTooLow(ClockId::$id))?;
+                    let timeout = clocks.peripheral(ClockId::SercomSlow).is_some();
+
+                    /* Enable clock for the SERCOM */
+                    pm.apbcmask.modify(|_, w| w.$sercom().set_bit());
+
+                    {
+                        let i2cm = sercom.i2cm();
+
+                        /* Reset the SERCOM */
+                        i2cm.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while i2cm.ctrla.read().swrst().bit_is_set() {}
+                        while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                        i2cm.ctrla
+                            .write(|w| w.mode().i2c_master().lowtout().bit(timeout));
+
+                        /* The same BAUD for high and low time */
+                        i2cm.baud.write(|w| unsafe { w.baud().bits(baud) });
+
+                        /* Enable */
+                        i2cm.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                        /* The bus state is unknown after enabling, assume it's idle */
+                        i2cm.status.write(|w| unsafe { w.busstate().bits(BusState::Idle as u8) });
+
+                        /* And wait */
+                        while i2cm.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(I2c { sercom, sda, scl })
+                }
+
+                pub fn bus_state(&self) -> BusState {
+                    match self.sercom.i2cm().status.read().busstate().bits() {
+                        1 => BusState::Idle,
+                        2 => BusState::Owner,
+                        3 => BusState::Busy,
+                        _ => BusState::Unknown,
+                    }
+                }
+
+                /* Wait for the current address or data byte to finish, returns whether the
+                 * slave is now sending */
+                fn wait(&mut self) -> Result<bool, Error> {
+                    let i2cm = self.sercom.i2cm();
+
+                    let read = loop {
+                        let flags = i2cm.intflag.read();
+                        if flags.sb().bit_is_set() {
+                            break true;
+                        }
+                        if flags.mb().bit_is_set() {
+                            break false;
+                        }
+                    };
+
+                    let status = i2cm.status.read();
+                    if status.buserr().bit_is_set() {
+                        i2cm.status.write(|w| w.buserr().set_bit());
+                        return Err(Error::BusError);
+                    }
+                    if status.arblost().bit_is_set() {
+                        i2cm.status.write(|w| w.arblost().set_bit());
+                        return Err(Error::ArbitrationLost);
+                    }
+                    if status.lowtout().bit_is_set() {
+                        i2cm.status.write(|w| w.lowtout().set_bit());
+                        return Err(Error::SclLowTimeout);
+                    }
+                    if status.rxnack().bit_is_set() {
+                        self.stop();
+                        return Err(Error::Nack);
+                    }
+
+                    Ok(read)
+                }
+
+                /* Send a (repeated) start condition with the address byte */
+                fn send_address(&mut self, byte: u8) -> Result<(), Error> {
+                    let i2cm = self.sercom.i2cm();
+
+                    i2cm.addr.write(|w| unsafe { w.addr().bits(byte) });
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                    self.wait().map(|_| ())
+                }
+
+                fn send_byte(&mut self, byte: u8) -> Result<(), Error> {
+                    let i2cm = self.sercom.i2cm();
+
+                    i2cm.data.write(|w| unsafe { w.data().bits(byte) });
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                    self.wait().map(|_| ())
+                }
+
+                fn command(&mut self, cmd: u8, nack: bool) {
+                    let i2cm = self.sercom.i2cm();
+
+                    i2cm.ctrlb
+                        .modify(|_, w| unsafe { w.ackact().bit(nack).cmd().bits(cmd) });
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+                }
+
+                fn stop(&mut self) {
+                    self.command(CMD_STOP, true);
+                }
+
+                /* Address `address` for writing. A 10 bit address takes two bytes */
+                fn start_write(&mut self, address: Address) -> Result<(), Error> {
+                    match address {
+                        Address::SevenBit(address) => self.send_address(address << 1),
+                        Address::TenBit(address) => {
+                            self.send_address(ten_bit_header(address))?;
+                            self.send_byte(address as u8)
+                        }
+                    }
+                }
+
+                /* Address `address` for reading. After a write to a 10 bit address the header
+                 * byte on a repeated start is enough, otherwise the full address is sent first */
+                fn start_read(&mut self, address: Address, addressed: bool) -> Result<(), Error> {
+                    let byte = match address {
+                        Address::SevenBit(address) => address << 1 | 1,
+                        Address::TenBit(address) => {
+                            if !addressed {
+                                self.start_write(Address::TenBit(address))?;
+                            }
+                            ten_bit_header(address) | 1
+                        }
+                    };
+
+                    let i2cm = self.sercom.i2cm();
+
+                    i2cm.addr.write(|w| unsafe { w.addr().bits(byte) });
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                    /* SB is set once the first byte has been received */
+                    self.wait().map(|_| ())
+                }
+
+                fn write_bytes(&mut self, bytes: &[u8]) -> Result<(), Error> {
+                    for byte in bytes {
+                        self.send_byte(*byte)?;
+                    }
+
+                    Ok(())
+                }
+
+                /* Every byte but the last is acknowledged, the last is answered with a NACK and
+                 * a stop condition */
+                fn read_bytes(&mut self, buffer: &mut [u8]) -> Result<(), Error> {
+                    let count = buffer.len();
+
+                    for (i, byte) in buffer.iter_mut().enumerate() {
+                        if i + 1 == count {
+                            self.stop();
+                            *byte = self.sercom.i2cm().data.read().data().bits();
+                        } else {
+                            *byte = self.sercom.i2cm().data.read().data().bits();
+                            self.command(CMD_READ, false);
+                            self.wait()?;
+                        }
+                    }
+
+                    Ok(())
+                }
+
+                pub fn write_to(&mut self, address: Address, bytes: &[u8]) -> Result<(), Error> {
+                    self.start_write(address)?;
+                    self.write_bytes(bytes)?;
+                    self.stop();
+
+                    Ok(())
+                }
+
+                /* A read always transfers at least one byte, so nothing happens on the bus for an
+                 * empty `buffer` */
+                pub fn read_from(&mut self, address: Address, buffer: &mut [u8]) -> Result<(), Error> {
+                    if buffer.is_empty() {
+                        return Ok(());
+                    }
+
+                    self.start_read(address, false)?;
+                    self.read_bytes(buffer)
+                }
+
+                /* Write `bytes` and read back into `buffer` after a repeated start, only the write
+                 * happens for an empty `buffer` */
+                pub fn write_read_from(
+                    &mut self,
+                    address: Address,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Error> {
+                    if buffer.is_empty() {
+                        return self.write_to(address, bytes);
+                    }
+
+                    self.start_write(address)?;
+                    self.write_bytes(bytes)?;
+                    self.start_read(address, true)?;
+                    self.read_bytes(buffer)
+                }
+
+                /* Keep the I2C master running in standby, which needs its generic clock to run in
+                 * standby as well. The SERCOM is briefly disabled, cutting short a transfer in
+                 * progress */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::sercom_run_in_standby(&self.sercom, run_in_standby);
+
+                    let i2cm = self.sercom.i2cm();
+
+                    /* The bus state is unknown after enabling again, assume it's idle */
+                    i2cm.status.write(|w| unsafe { w.busstate().bits(BusState::Idle as u8) });
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Hand back SERCOM and pins after disabling the I2C master */
+                pub fn free(self, pm: &PM) -> ($SERCOM, SDA, SCL) {
+                    let i2cm = self.sercom.i2cm();
+
+                    i2cm.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while i2cm.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$sercom().clear_bit());
+
+                    (self.sercom, self.sda, self.scl)
+                }
+            }
+
+            impl<SDA, SCL> Write for I2c<$SERCOM, SDA, SCL>
+            where
+                SDA: SercomPad<$SERCOM, Pad = Pad0>,
+                SCL: SercomPad<$SERCOM, Pad = Pad1>,
+            {
+                type Error = Error;
+
+                fn write(&mut self, address: u8, bytes: &[u8]) -> Result<(), Error> {
+                    self.write_to(Address::SevenBit(address), bytes)
+                }
+            }
+
+            impl<SDA, SCL> Read for I2c<$SERCOM, SDA, SCL>
+            where
+                SDA: SercomPad<$SERCOM, Pad = Pad0>,
+                SCL: SercomPad<$SERCOM, Pad = Pad1>,
+            {
+                type Error = Error;
+
+                fn read(&mut self, address: u8, buffer: &mut [u8]) -> Result<(), Error> {
+                    self.read_from(Address::SevenBit(address), buffer)
+                }
+            }
+
+            impl<SDA, SCL> WriteRead for I2c<$SERCOM, SDA, SCL>
+            where
+                SDA: SercomPad<$SERCOM, Pad = Pad0>,
+                SCL: SercomPad<$SERCOM, Pad = Pad1>,
+            {
+                type Error = Error;
+
+                fn write_read(
+                    &mut self,
+                    address: u8,
+                    bytes: &[u8],
+                    buffer: &mut [u8],
+                ) -> Result<(), Error> {
+                    self.write_read_from(Address::SevenBit(address), bytes, buffer)
+                }
+            }
+        )+
+    };
+}
+
+i2c!(
+    SERCOM0: (sercom0, Sercom0Core),
+    SERCOM1: (sercom1, Sercom1Core),
+    SERCOM2: (sercom2, Sercom2Core),
+    SERCOM3: (sercom3, Sercom3Core),
+);
+
+/* Addresses a slave answers to */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SlaveAddress {
+    Single(u8),
+    /* Match `address` on the bits set in `mask` */
+    Mask { address: u8, mask: u8 },
+    TwoAddresses(u8, u8),
+}
+
+/* Registers a slave exposes to the master, EEPROM style: the first byte of a write sets the
+ * register pointer, the following bytes are written to consecutive registers and reads continue
+ * from the register pointer */
+#[derive(Clone, Copy)]
+pub struct RegisterMap {
+    read: fn(u8) -> u8,
+    write: fn(u8, u8),
+    stop: Option<fn()>,
+}
+
+impl RegisterMap {
+    /* `read` gives the value of a register, `write` gets the register and its new value */
+    pub fn new(read: fn(u8) -> u8, write: fn(u8, u8)) -> RegisterMap {
+        RegisterMap {
+            read,
+            write,
+            stop: None,
+        }
+    }
+
+    /* Called on the stop condition ending a transaction with us, e.g. to apply a batch of
+     * register writes at once */
+    pub fn on_stop(mut self, stop: fn()) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+}
+
+/* Register state of the slaves on SERCOM0-3 */
+#[derive(Clone, Copy)]
+struct SlaveState {
+    map: Option<RegisterMap>,
+    pointer: u8,
+    /* The next byte written by the master is the register pointer */
+    expect_pointer: bool,
+    /* Nothing has been sent in the current read yet */
+    first_read: bool,
+}
+
+static mut SLAVES: [SlaveState; 4] = [SlaveState {
+    map: None,
+    pointer: 0,
+    expect_pointer: false,
+    first_read: false,
+}; 4];
+
+/* Interrupt-driven I2C slave with SDA on PAD0 and SCL on PAD1. The SERCOM stretches SCL until an
+ * address match or byte has been handled, so the register map callbacks may take their time */
+pub struct I2cSlave<SERCOM, SDA, SCL> {
+    sercom: SERCOM,
+    sda: SDA,
+    scl: SCL,
+}
+
+macro_rules! i2c_slave {
+    ($($SERCOM:ident: ($sercom:ident, $n:expr, $id:ident, $handle_interrupt:ident),)+) => {
+        $(
+            impl<SDA, SCL> I2cSlave<$SERCOM, SDA, SCL>
+            where
+                SDA: SercomPad<$SERCOM, Pad = Pad0>,
+                SCL: SercomPad<$SERCOM, Pad = Pad1>,
+            {
+                /* Take over the SERCOM, which needs a generic clock routed to its core channel,
+                 * and serve `map` at `address`. The SCL low timeout is only detected if the slow
+                 * SERCOM clock is routed too */
+                pub fn $sercom(
+                    sercom: $SERCOM,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    address: SlaveAddress,
+                    map: RegisterMap,
+                    sda: SDA,
+                    scl: SCL,
+                ) -> Result<I2cSlave<$SERCOM, SDA, SCL>, clock::Error> {
+                    clocks.require(ClockId::$id)?;
+                    let timeout = clocks.peripheral(ClockId::SercomSlow).is_some();
+
+                    /* Enable clock for the SERCOM */
+                    pm.apbcmask.modify(|_, w| w.$sercom().set_bit());
+
+                    {
+                        let i2cs = sercom.i2cs();
+
+                        /* Reset the SERCOM */
+                        i2cs.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while i2cs.ctrla.read().swrst().bit_is_set() {}
+                        while i2cs.status.read().syncbusy().bit_is_set() {}
+
+                        i2cs.ctrla.write(|w| w.mode().i2c_slave().lowtout().bit(timeout));
+
+                        /* Smart mode acknowledges written bytes on reading DATA and sends read bytes
+                         * on writing DATA */
+                        i2cs.ctrlb.write(|w| {
+                            let w = w.smen().set_bit();
+                            match address {
+                                SlaveAddress::TwoAddresses(_, _) => unsafe {
+                                    w.amode().bits(AMODE_2ADDR)
+                                },
+                                _ => unsafe { w.amode().bits(AMODE_MASK) },
+                            }
+                        });
+
+                        let (addr, addrmask) = match address {
+                            SlaveAddress::Single(address) => (address, 0),
+                            SlaveAddress::Mask { address, mask } => (address, !mask & 0x7F),
+                            SlaveAddress::TwoAddresses(first, second) => (first, second),
+                        };
+                        i2cs.addr
+                            .write(|w| unsafe { w.addr().bits(addr).addrmask().bits(addrmask) });
+
+                        interrupt::free(|cs| {
+                            let nvic = NVIC.borrow(cs);
+
+                            unsafe {
+                                SLAVES[$n] = SlaveState {
+                                    map: Some(map),
+                                    pointer: 0,
+                                    expect_pointer: false,
+                                    first_read: false,
+                                };
+                            }
+
+                            i2cs.intenset
+                                .write(|w| w.amatch().set_bit().drdy().set_bit().prec().set_bit());
+
+                            /* Enable SERCOM IRQs */
+                            nvic.enable(Interrupt::$SERCOM);
+                        });
+
+                        /* Enable */
+                        i2cs.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while i2cs.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(I2cSlave { sercom, sda, scl })
+                }
+
+                /* Keep the I2C slave running in standby, which needs its generic clock to run in
+                 * standby as well. The SERCOM is briefly disabled, cutting short a transfer in
+                 * progress */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::sercom_run_in_standby(&self.sercom, run_in_standby);
+                }
+
+                /* Hand back SERCOM and pins after disabling the I2C slave */
+                pub fn free(self, pm: &PM) -> ($SERCOM, SDA, SCL) {
+                    let i2cs = self.sercom.i2cs();
+
+                    interrupt::free(|_| unsafe {
+                        i2cs.intenclr.write(|w| w.bits(0xFF));
+                        SLAVES[$n].map = None;
+                    });
+
+                    i2cs.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while i2cs.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$sercom().clear_bit());
+
+                    (self.sercom, self.sda, self.scl)
+                }
+            }
+
+            /* To be called from the interrupt handler of the SERCOM */
+            pub fn $handle_interrupt() {
+                let i2cs = unsafe { (*$SERCOM::ptr()).i2cs() };
+                let state = unsafe { &mut SLAVES[$n] };
+                let map = match state.map {
+                    Some(map) => map,
+                    None => return,
+                };
+                let flags = i2cs.intflag.read();
+                let status = i2cs.status.read();
+
+                if flags.amatch().bit_is_set() {
+                    /* A new transaction, forget errors of the previous one */
+                    i2cs.status
+                        .write(|w| w.buserr().set_bit().coll().set_bit().lowtout().set_bit());
+
+                    if status.dir().bit_is_set() {
+                        state.first_read = true;
+                    } else {
+                        state.expect_pointer = true;
+                    }
+
+                    /* Acknowledge the address */
+                    i2cs.ctrlb.modify(|_, w| unsafe {
+                        w.ackact().clear_bit().cmd().bits(CMD_CONTINUE)
+                    });
+                } else if flags.drdy().bit_is_set() {
+                    if status.dir().bit_is_set() {
+                        /* The master answers the last byte it wants with a NACK */
+                        if !state.first_read && status.rxnack().bit_is_set() {
+                            i2cs.ctrlb
+                                .modify(|_, w| unsafe { w.cmd().bits(CMD_WAIT_START) });
+                        } else {
+                            state.first_read = false;
+                            let value = (map.read)(state.pointer);
+                            state.pointer = state.pointer.wrapping_add(1);
+                            i2cs.data.write(|w| unsafe { w.data().bits(value) });
+                        }
+                    } else {
+                        let value = i2cs.data.read().data().bits();
+                        if state.expect_pointer {
+                            state.expect_pointer = false;
+                            state.pointer = value;
+                        } else {
+                            (map.write)(state.pointer, value);
+                            state.pointer = state.pointer.wrapping_add(1);
+                        }
+                    }
+                }
+
+                if flags.prec().bit_is_set() {
+                    i2cs.intflag.write(|w| w.prec().set_bit());
+
+                    if let Some(stop) = map.stop {
+                        stop();
+                    }
+                }
+            }
+
+            impl<SDA, SCL> WakeSource for I2cSlave<$SERCOM, SDA, SCL> {
+                fn is_armed(&self, pm: &PM) -> bool {
+                    power::bus_clock_enabled(pm, &self.sercom)
+                        && power::nvic_enabled(Interrupt::$SERCOM)
+                        && self.sercom.i2cs().intenset.read().bits() != 0
+                }
+            }
+        )+
+    };
+}
+
+i2c_slave!(
+    SERCOM0: (sercom0, 0, Sercom0Core, handle_slave_interrupt_sercom0),
+    SERCOM1: (sercom1, 1, Sercom1Core, handle_slave_interrupt_sercom1),
+    SERCOM2: (sercom2, 2, Sercom2Core, handle_slave_interrupt_sercom2),
+    SERCOM3: (sercom3, 3, Sercom3Core, handle_slave_interrupt_sercom3),
+);
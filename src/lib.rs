@@ -1,17 +1,38 @@
 #![no_std]
-#![cfg_attr(feature = "rt", feature(global_asm))]
-#![cfg_attr(feature = "rt", feature(used))]
 #![feature(const_fn)]
 #![allow(non_camel_case_types)]
 
 extern crate bare_metal;
 extern crate cortex_m;
 extern crate cortex_m_rt;
+extern crate embedded_hal;
+extern crate nb;
 extern crate vcell;
+extern crate void;
 
+pub mod ac;
+pub mod adc;
+pub mod capture;
+pub mod clock;
 mod common;
+pub mod dac;
+pub mod delay;
+pub mod eic;
+pub mod evsys;
+pub mod gpio;
+pub mod i2c;
+pub mod pmux;
+pub mod power;
+pub mod pwm;
+mod ringbuf;
 pub mod snowflake;
+pub mod spi;
 mod svd;
+pub mod system;
+pub mod time;
+pub mod timer;
+pub mod usart;
+pub mod wdt;
 
 pub use common::*;
 pub use cortex_m_rt::*;
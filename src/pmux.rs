@@ -0,0 +1,335 @@
+/* Peripheral multiplexing table of the ATSAMD20E (32 pin package, so there's no PA12, PA13, PA20,
+ * PA21, PA26 and PA29).
+ *
+ * Every peripheral role a pin can take is a trait which is only implemented for a pin that has
+ * been switched to the right peripheral function, e.g. `Pa25<PfA>` is `ExtIntPin` for EXTINT13
+ * and `Pa25<PfC>` is `SercomPad<SERCOM3>` for PAD3. Drivers require these traits for the pins
+ * they are handed, so using a pin with a function it doesn't have won't compile. Use
+ * `into_role` with one of the role markers below to configure PMUX and PMUXEN for a role without
+ * spelling out the function */
+
+use core::marker::PhantomData;
+
+use super::clock::Generator;
+use super::gpio::*;
+use super::{SERCOM0, SERCOM1, SERCOM2, SERCOM3, TC0, TC1, TC2, TC3, TC4, TC5};
+
+/* EIC external interrupt line */
+pub trait ExtIntPin {
+    const EXTINT: u8;
+}
+
+/* EIC non-maskable interrupt */
+pub trait NmiPin {}
+
+/* ADC analog input */
+pub trait AinPin {
+    const AIN: u8;
+}
+
+/* ADC analog input which can be the negative input of a differential conversion, AIN0-7 */
+pub trait NegAinPin: AinPin {}
+
+/* ADC and DAC reference A */
+pub trait VrefAPin {}
+
+/* ADC reference B */
+pub trait VrefBPin {}
+
+/* DAC output */
+pub trait VoutPin {}
+
+/* AC analog input */
+pub trait AcAinPin {
+    const AIN: u8;
+}
+
+/* AC comparator output */
+pub trait AcCmpPin {
+    const CMP: u8;
+}
+
+/* SERCOM pad, `Pad` is one of `Pad0` to `Pad3` so drivers can restrict the pads of their pins */
+pub trait SercomPad<SERCOM> {
+    const PAD: u8;
+    type Pad: PadNumber;
+}
+
+/* Pad numbers for `SercomPad::Pad` */
+pub trait PadNumber {
+    const NUMBER: u8;
+}
+
+pub struct Pad0;
+pub struct Pad1;
+pub struct Pad2;
+pub struct Pad3;
+
+/* TC waveform output */
+pub trait TcWoPin<TC> {
+    const WO: u8;
+}
+
+/* Generic clock generator input/output */
+pub trait GclkIoPin {
+    const GCLK: Generator;
+}
+
+/* Role markers for `into_role` */
+pub struct ExtInt;
+pub struct Nmi;
+pub struct Ain;
+pub struct VrefA;
+pub struct VrefB;
+pub struct Vout;
+pub struct AcAin;
+pub struct AcCmp;
+pub struct GclkIo;
+
+pub struct Pad<SERCOM> {
+    _sercom: PhantomData<SERCOM>,
+}
+
+pub struct Wo<TC> {
+    _tc: PhantomData<TC>,
+}
+
+/* Switch `pin` to the peripheral function role `R` requires, e.g.
+ * `into_role::<_, Pad<SERCOM0>>(pa4, &mut port)` gives `Pa4<PfD>` and won't compile for a pin
+ * without a SERCOM0 pad */
+pub fn into_role<PIN, R>(pin: PIN, port: &mut Port) -> PIN::Output
+where
+    PIN: IntoFunction<R>,
+{
+    pin.into_role(port)
+}
+
+/* Implemented for pins in any mode that can take role `R`, `Output` is the configured pin */
+pub trait IntoFunction<R> {
+    type Output;
+
+    fn into_role(self, port: &mut Port) -> Self::Output;
+}
+
+macro_rules! into_function {
+    ($PAi:ident, $Pf:ident, $Role:ty) => {
+        impl<MODE> IntoFunction<$Role> for $PAi<MODE> {
+            type Output = $PAi<$Pf>;
+
+            fn into_role(self, port: &mut Port) -> $PAi<$Pf> {
+                self.into_function(port)
+            }
+        }
+    };
+}
+
+macro_rules! marker {
+    ($Role:ident, $Marker:ident: [$(($PAi:ident, $Pf:ident),)+]) => {
+        $(
+            impl $Role for $PAi<$Pf> {}
+            into_function!($PAi, $Pf, $Marker);
+        )+
+    };
+}
+
+macro_rules! numbered {
+    ($Role:ident, $Marker:ident, $CONST:ident: $T:ty, [$(($PAi:ident, $Pf:ident, $n:expr),)+]) => {
+        $(
+            impl $Role for $PAi<$Pf> {
+                const $CONST: $T = $n;
+            }
+            into_function!($PAi, $Pf, $Marker);
+        )+
+    };
+}
+
+macro_rules! instanced {
+    ($Role:ident, $Marker:ident, $CONST:ident, [$(($PAi:ident, $Pf:ident, $P:ident, $n:expr),)+]) => {
+        $(
+            impl $Role<$P> for $PAi<$Pf> {
+                const $CONST: u8 = $n;
+            }
+            into_function!($PAi, $Pf, $Marker<$P>);
+        )+
+    };
+}
+
+numbered!(
+    ExtIntPin,
+    ExtInt,
+    EXTINT: u8,
+    [
+        (Pa0, PfA, 0),
+        (Pa1, PfA, 1),
+        (Pa2, PfA, 2),
+        (Pa3, PfA, 3),
+        (Pa4, PfA, 4),
+        (Pa5, PfA, 5),
+        (Pa6, PfA, 6),
+        (Pa7, PfA, 7),
+        (Pa9, PfA, 9),
+        (Pa10, PfA, 10),
+        (Pa11, PfA, 11),
+        (Pa14, PfA, 14),
+        (Pa15, PfA, 15),
+        (Pa16, PfA, 0),
+        (Pa17, PfA, 1),
+        (Pa18, PfA, 2),
+        (Pa19, PfA, 3),
+        (Pa22, PfA, 6),
+        (Pa23, PfA, 7),
+        (Pa24, PfA, 12),
+        (Pa25, PfA, 13),
+        (Pa27, PfA, 15),
+        (Pa28, PfA, 8),
+        (Pa30, PfA, 10),
+        (Pa31, PfA, 11),
+    ]
+);
+
+marker!(NmiPin, Nmi: [(Pa8, PfA),]);
+
+numbered!(
+    AinPin,
+    Ain,
+    AIN: u8,
+    [
+        (Pa2, PfB, 0),
+        (Pa3, PfB, 1),
+        (Pa4, PfB, 4),
+        (Pa5, PfB, 5),
+        (Pa6, PfB, 6),
+        (Pa7, PfB, 7),
+        (Pa8, PfB, 16),
+        (Pa9, PfB, 17),
+        (Pa10, PfB, 18),
+        (Pa11, PfB, 19),
+    ]
+);
+
+/* Same pin function as `AinPin`, so there's no role of its own to switch into */
+impl NegAinPin for Pa2<PfB> {}
+impl NegAinPin for Pa3<PfB> {}
+impl NegAinPin for Pa4<PfB> {}
+impl NegAinPin for Pa5<PfB> {}
+impl NegAinPin for Pa6<PfB> {}
+impl NegAinPin for Pa7<PfB> {}
+
+marker!(VrefAPin, VrefA: [(Pa3, PfB),]);
+marker!(VrefBPin, VrefB: [(Pa4, PfB),]);
+marker!(VoutPin, Vout: [(Pa2, PfB),]);
+
+numbered!(
+    AcAinPin,
+    AcAin,
+    AIN: u8,
+    [(Pa4, PfB, 0), (Pa5, PfB, 1), (Pa6, PfB, 2), (Pa7, PfB, 3),]
+);
+
+numbered!(AcCmpPin, AcCmp, CMP: u8, [(Pa18, PfH, 0), (Pa19, PfH, 1),]);
+
+macro_rules! pad_numbers {
+    ($($Pad:ident: $n:expr,)+) => {
+        $(
+            impl PadNumber for $Pad {
+                const NUMBER: u8 = $n;
+            }
+        )+
+    };
+}
+
+pad_numbers!(Pad0: 0, Pad1: 1, Pad2: 2, Pad3: 3,);
+
+macro_rules! pads {
+    ([$(($PAi:ident, $Pf:ident, $SERCOM:ident, $Pad:ident),)+]) => {
+        $(
+            impl SercomPad<$SERCOM> for $PAi<$Pf> {
+                const PAD: u8 = $Pad::NUMBER;
+                type Pad = $Pad;
+            }
+            into_function!($PAi, $Pf, Pad<$SERCOM>);
+        )+
+    };
+}
+
+pads!([
+    (Pa0, PfD, SERCOM1, Pad0),
+    (Pa1, PfD, SERCOM1, Pad1),
+    (Pa4, PfD, SERCOM0, Pad0),
+    (Pa5, PfD, SERCOM0, Pad1),
+    (Pa6, PfD, SERCOM0, Pad2),
+    (Pa7, PfD, SERCOM0, Pad3),
+    (Pa8, PfC, SERCOM0, Pad0),
+    (Pa9, PfC, SERCOM0, Pad1),
+    (Pa10, PfC, SERCOM0, Pad2),
+    (Pa11, PfC, SERCOM0, Pad3),
+    (Pa8, PfD, SERCOM2, Pad0),
+    (Pa9, PfD, SERCOM2, Pad1),
+    (Pa10, PfD, SERCOM2, Pad2),
+    (Pa11, PfD, SERCOM2, Pad3),
+    (Pa14, PfC, SERCOM2, Pad2),
+    (Pa15, PfC, SERCOM2, Pad3),
+    (Pa16, PfC, SERCOM1, Pad0),
+    (Pa17, PfC, SERCOM1, Pad1),
+    (Pa18, PfC, SERCOM1, Pad2),
+    (Pa19, PfC, SERCOM1, Pad3),
+    (Pa16, PfD, SERCOM3, Pad0),
+    (Pa17, PfD, SERCOM3, Pad1),
+    (Pa18, PfD, SERCOM3, Pad2),
+    (Pa19, PfD, SERCOM3, Pad3),
+    (Pa22, PfC, SERCOM3, Pad0),
+    (Pa23, PfC, SERCOM3, Pad1),
+    (Pa24, PfC, SERCOM3, Pad2),
+    (Pa25, PfC, SERCOM3, Pad3),
+    (Pa30, PfD, SERCOM1, Pad2),
+    (Pa31, PfD, SERCOM1, Pad3),
+]);
+
+instanced!(
+    TcWoPin,
+    Wo,
+    WO,
+    [
+        (Pa0, PfE, TC2, 0),
+        (Pa1, PfE, TC2, 1),
+        (Pa4, PfE, TC0, 0),
+        (Pa5, PfE, TC0, 1),
+        (Pa6, PfE, TC1, 0),
+        (Pa7, PfE, TC1, 1),
+        (Pa8, PfE, TC0, 0),
+        (Pa9, PfE, TC0, 1),
+        (Pa10, PfE, TC1, 0),
+        (Pa11, PfE, TC1, 1),
+        (Pa14, PfE, TC3, 0),
+        (Pa15, PfE, TC3, 1),
+        (Pa16, PfE, TC2, 0),
+        (Pa17, PfE, TC2, 1),
+        (Pa18, PfE, TC3, 0),
+        (Pa19, PfE, TC3, 1),
+        (Pa22, PfE, TC4, 0),
+        (Pa23, PfE, TC4, 1),
+        (Pa24, PfE, TC5, 0),
+        (Pa25, PfE, TC5, 1),
+        (Pa30, PfE, TC1, 0),
+        (Pa31, PfE, TC1, 1),
+    ]
+);
+
+numbered!(
+    GclkIoPin,
+    GclkIo,
+    GCLK: Generator,
+    [
+        (Pa10, PfH, Generator::Gclk4),
+        (Pa11, PfH, Generator::Gclk5),
+        (Pa14, PfH, Generator::Gclk0),
+        (Pa15, PfH, Generator::Gclk1),
+        (Pa16, PfH, Generator::Gclk2),
+        (Pa17, PfH, Generator::Gclk3),
+        (Pa22, PfH, Generator::Gclk6),
+        (Pa23, PfH, Generator::Gclk7),
+        (Pa27, PfH, Generator::Gclk0),
+        (Pa28, PfH, Generator::Gclk0),
+        (Pa30, PfH, Generator::Gclk0),
+    ]
+);
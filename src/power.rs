@@ -0,0 +1,270 @@
+use super::clock::{ClockSource, Generator};
+use super::{gclk, sercom0, tc0};
+use super::{Interrupt, AC, ADC, DAC, EIC, GCLK, NVIC, PM, RTC, SCB, SYSCTRL, WDT};
+use super::{DSU, EVSYS, PORT};
+use super::{SERCOM0, SERCOM1, SERCOM2, SERCOM3, TC0, TC1, TC2, TC3, TC4, TC5};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use vcell::VolatileCell;
+
+/* SLEEPDEEP bit of the System Control Register */
+const SCR_SLEEPDEEP: u32 = 1 << 2;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum SleepMode {
+    /* CPU clock stopped */
+    Idle0,
+    /* CPU and AHB clocks stopped */
+    Idle1,
+    /* CPU, AHB and APB clocks stopped */
+    Idle2,
+    /* All clocks stopped except for those running in standby, wakes up on asynchronous
+     * interrupts only */
+    Standby,
+}
+
+/* Sleep until an interrupt wakes us up, without arming any wake source on our own. SysTick runs
+ * from the CPU clock and so can't wake us up from any of the modes, use a TC or the RTC instead */
+pub fn sleep(pm: &PM, mode: SleepMode) {
+    interrupt::free(|cs| {
+        let scb = SCB.borrow(cs);
+
+        match mode {
+            SleepMode::Idle0 => pm.sleep.write(|w| w.idle().cpu()),
+            SleepMode::Idle1 => pm.sleep.write(|w| w.idle().ahb()),
+            SleepMode::Idle2 => pm.sleep.write(|w| w.idle().apb()),
+            SleepMode::Standby => {}
+        }
+
+        unsafe {
+            scb.scr.modify(|r| match mode {
+                SleepMode::Standby => r | SCR_SLEEPDEEP,
+                _ => r & !SCR_SLEEPDEEP,
+            })
+        };
+    });
+
+    /* Make sure all writes are done before the clocks stop */
+    cortex_m::asm::dsb();
+    cortex_m::asm::wfi();
+}
+
+/* Keep `source` running in standby and/or only run it while a peripheral requests it. The
+ * OSCULP32K always runs and GCLK1 isn't an oscillator, so both are left alone */
+pub fn set_oscillator_standby(
+    sysctrl: &SYSCTRL,
+    source: ClockSource,
+    run_in_standby: bool,
+    on_demand: bool,
+) {
+    match source {
+        ClockSource::Xosc => sysctrl
+            .xosc
+            .modify(|_, w| w.runstdby().bit(run_in_standby).ondemand().bit(on_demand)),
+        ClockSource::Osc32k => sysctrl
+            .osc32k
+            .modify(|_, w| w.runstdby().bit(run_in_standby).ondemand().bit(on_demand)),
+        ClockSource::Xosc32k => sysctrl
+            .xosc32k
+            .modify(|_, w| w.runstdby().bit(run_in_standby).ondemand().bit(on_demand)),
+        ClockSource::Osc8m => sysctrl
+            .osc8m
+            .modify(|_, w| w.runstdby().bit(run_in_standby).ondemand().bit(on_demand)),
+        ClockSource::Dfll48m => {
+            /* The DFLL48M can only be written while it's ready */
+            while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+
+            sysctrl
+                .dfllctrl
+                .modify(|_, w| w.runstdby().bit(run_in_standby).ondemand().bit(on_demand));
+
+            /* And wait */
+            while sysctrl.pclksr.read().dfllrdy().bit_is_clear() {}
+        }
+        ClockSource::OscUlp32k | ClockSource::Gclk1 => {}
+    }
+}
+
+/* Keep generator `gen` running in standby, its source has to run in standby as well */
+pub fn set_generator_standby(gclk: &GCLK, gen: Generator, run_in_standby: bool) {
+    /* Select the generator by writing its ID alone, so GENCTRL reads back its configuration. The
+     * ID is the lowest byte of the register */
+    let genctrl_id =
+        unsafe { &*(&gclk.genctrl as *const gclk::GENCTRL as *const VolatileCell<u8>) };
+    genctrl_id.set(gen.id());
+
+    /* And wait */
+    while gclk.status.read().syncbusy().bit_is_set() {}
+
+    gclk.genctrl.modify(|_, w| w.runstdby().bit(run_in_standby));
+
+    /* And wait */
+    while gclk.status.read().syncbusy().bit_is_set() {}
+}
+
+/* APB bridge a peripheral hangs off, each with its own mask register in the PM */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Bus {
+    Apba,
+    Apbb,
+    Apbc,
+}
+
+/* Peripherals whose bus clock can be gated, by bridge and bit in its mask register. PM, SYSCTRL,
+ * GCLK and NVMCTRL are left out as nothing works without them */
+pub trait BusClock {
+    const BUS: Bus;
+    const BIT: u8;
+    /* Bit in AHBMASK for peripherals with an AHB interface of their own */
+    const AHB_BIT: Option<u8> = None;
+}
+
+macro_rules! bus_clocks {
+    ($($P:ident: ($bus:ident, $bit:expr),)+) => {
+        $(
+            impl BusClock for $P {
+                const BUS: Bus = Bus::$bus;
+                const BIT: u8 = $bit;
+            }
+        )+
+    }
+}
+
+bus_clocks! {
+    WDT: (Apba, 4),
+    RTC: (Apba, 5),
+    EIC: (Apba, 6),
+    PORT: (Apbb, 3),
+    EVSYS: (Apbc, 1),
+    SERCOM0: (Apbc, 2),
+    SERCOM1: (Apbc, 3),
+    SERCOM2: (Apbc, 4),
+    SERCOM3: (Apbc, 5),
+    TC0: (Apbc, 8),
+    TC1: (Apbc, 9),
+    TC2: (Apbc, 10),
+    TC3: (Apbc, 11),
+    TC4: (Apbc, 12),
+    TC5: (Apbc, 13),
+    ADC: (Apbc, 16),
+    AC: (Apbc, 17),
+    DAC: (Apbc, 18),
+}
+
+impl BusClock for DSU {
+    const BUS: Bus = Bus::Apbb;
+    const BIT: u8 = 1;
+    const AHB_BIT: Option<u8> = Some(3);
+}
+
+/* Set or clear `bit` of a mask register */
+macro_rules! set_mask_bit {
+    ($register:expr, $bit:expr, $enable:expr) => {
+        $register.modify(|r, w| unsafe {
+            w.bits(if $enable {
+                r.bits() | 1 << $bit
+            } else {
+                r.bits() & !(1 << $bit)
+            })
+        })
+    };
+}
+
+/* Enable or gate the bus clocks of `peripheral`. Registers can't be accessed while the clock is
+ * gated, so this takes the peripheral itself: the drivers own theirs, enable the bus clock in
+ * their constructor and gate it again in `free`. That leaves this for the peripherals clocked
+ * from reset which aren't in use, e.g. the DSU, RTC or an unused WDT */
+pub fn set_bus_clock<P: BusClock>(pm: &PM, _peripheral: &P, enable: bool) {
+    match P::BUS {
+        Bus::Apba => set_mask_bit!(pm.apbamask, P::BIT, enable),
+        Bus::Apbb => set_mask_bit!(pm.apbbmask, P::BIT, enable),
+        Bus::Apbc => set_mask_bit!(pm.apbcmask, P::BIT, enable),
+    }
+
+    if let Some(bit) = P::AHB_BIT {
+        set_mask_bit!(pm.ahbmask, bit, enable);
+    }
+}
+
+/* Whether the bus clock of `peripheral` is running, i.e. its registers can be accessed */
+pub fn bus_clock_enabled<P: BusClock>(pm: &PM, _peripheral: &P) -> bool {
+    let mask = match P::BUS {
+        Bus::Apba => pm.apbamask.read().bits(),
+        Bus::Apbb => pm.apbbmask.read().bits(),
+        Bus::Apbc => pm.apbcmask.read().bits(),
+    };
+
+    mask & 1 << P::BIT != 0
+}
+
+/* RUNSTDBY is enable-protected, so disable the peripheral around the change */
+macro_rules! set_run_in_standby {
+    ($ctrla:expr, $status:expr, $enable:expr) => {{
+        let enabled = $ctrla.read().enable().bit_is_set();
+
+        $ctrla.modify(|_, w| w.enable().clear_bit());
+
+        /* And wait */
+        while $status.read().syncbusy().bit_is_set() {}
+
+        $ctrla.modify(|_, w| w.runstdby().bit($enable));
+        $ctrla.modify(|_, w| w.enable().bit(enabled));
+
+        /* And wait */
+        while $status.read().syncbusy().bit_is_set() {}
+    }};
+}
+
+/* Used by the drivers' `set_run_in_standby`, which also need their generic clock to keep running
+ * in standby. The WDT, RTC and EIC always run in standby */
+pub(crate) fn sercom_run_in_standby(sercom: &sercom0::RegisterBlock, enable: bool) {
+    /* CTRLA has the same layout in all modes as far as we are concerned */
+    let usart = sercom.usart();
+
+    set_run_in_standby!(usart.ctrla, usart.status, enable);
+}
+
+pub(crate) fn tc_run_in_standby(tc: &tc0::RegisterBlock, enable: bool) {
+    let count16 = tc.count16();
+
+    set_run_in_standby!(count16.ctrla, count16.status, enable);
+}
+
+pub(crate) fn adc_run_in_standby(adc: &ADC, enable: bool) {
+    set_run_in_standby!(adc.ctrla, adc.status, enable);
+}
+
+pub(crate) fn ac_run_in_standby(ac: &AC, enable: bool) {
+    set_run_in_standby!(ac.ctrla, ac.statusb, enable);
+}
+
+pub(crate) fn dac_run_in_standby(dac: &DAC, enable: bool) {
+    set_run_in_standby!(dac.ctrla, dac.status, enable);
+}
+
+/* Implemented by the drivers of the EIC, WDT, AC and interrupt driven SERCOMs, and by the RTC */
+pub trait WakeSource {
+    /* Whether the peripheral has its bus clock and an interrupt enabled both in itself and in the
+     * NVIC, and so may wake us up */
+    fn is_armed(&self, pm: &PM) -> bool;
+}
+
+/* Whether any of `sources` may wake us up, to check before going to sleep for good */
+pub fn any_armed(pm: &PM, sources: &[&dyn WakeSource]) -> bool {
+    sources.iter().any(|source| source.is_armed(pm))
+}
+
+pub(crate) fn nvic_enabled(irq: Interrupt) -> bool {
+    interrupt::free(|cs| NVIC.borrow(cs).is_enabled(irq))
+}
+
+/* There's no RTC driver, so the RTC is taken as it is */
+impl WakeSource for RTC {
+    fn is_armed(&self, pm: &PM) -> bool {
+        bus_clock_enabled(pm, self)
+            && nvic_enabled(Interrupt::RTC)
+            && self.mode0().intenset.read().bits() != 0
+    }
+}
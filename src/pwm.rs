@@ -0,0 +1,325 @@
+use super::clock::{ClockId, Clocks, Error};
+use super::pmux::TcWoPin;
+use super::power;
+use super::time::Hertz;
+use super::timer::{prescaler_and_period, PRESCALERS};
+use super::{PM, TC0, TC1, TC2, TC3, TC4, TC5};
+
+use embedded_hal;
+
+/* Waveform generation mode of the TC */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Mode {
+    /* NPWM: both WO[0] and WO[1], the period is 65536 prescaled ticks so only the prescaler
+     * steps are available as frequencies */
+    Normal,
+    /* MPWM: CC0 sets the period so any frequency is possible, but only WO[1] is usable */
+    Match,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Channel {
+    Wo0 = 0,
+    Wo1,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Polarity {
+    /* Output is high from the start of the period until the compare match */
+    Normal,
+    /* Output is low from the start of the period until the compare match */
+    Inverted,
+}
+
+/* Prescaler and TOP value closest to `freq` in `mode`, 0 Hz is taken as 1 Hz so it gives the
+ * longest period */
+fn prescaler_and_top(clock: Hertz, freq: Hertz, mode: Mode) -> (u8, u16) {
+    let ticks = clock.0 / freq.0.max(1);
+    match mode {
+        Mode::Normal => {
+            let prescaler = PRESCALERS
+                .iter()
+                .position(|prescaler| ticks / prescaler <= 0x1_0000)
+                .unwrap_or(PRESCALERS.len() - 1);
+            (prescaler as u8, 0xFFFF)
+        }
+        Mode::Match => prescaler_and_period(ticks),
+    }
+}
+
+/* Both waveform outputs of a TC, driven through the multiplexed `Pwm` trait */
+pub struct Pwm<TC> {
+    tc: TC,
+    clock: Hertz,
+    mode: Mode,
+    prescaler: u8,
+    top: u16,
+    duty: [u16; 2],
+    enabled: [bool; 2],
+}
+
+/* A single waveform output, created from a pin multiplexed to it. It borrows the `Pwm` so the
+ * period can't change and the TC can't be freed while it exists. Don't drive a channel through
+ * both this and the `Pwm` it was created from since they keep separate duty cycles */
+pub struct PwmOutput<'a, TC: 'a, PIN> {
+    pwm: &'a Pwm<TC>,
+    pin: PIN,
+    duty: u16,
+    enabled: bool,
+}
+
+macro_rules! pwm {
+    ($($TC:ident: ($tc:ident, $id:ident),)+) => {
+        $(
+            impl Pwm<$TC> {
+                /* Take over the TC for PWM with a period of `freq`, which needs a generic clock
+                 * routed to the channel of its pair. Both channels start out disabled */
+                pub fn $tc<F: Into<Hertz>>(
+                    tc: $TC,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    freq: F,
+                    mode: Mode,
+                ) -> Result<Pwm<$TC>, Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+                    let (prescaler, top) = prescaler_and_top(clock, freq.into(), mode);
+
+                    /* Enable clock for the TC */
+                    pm.apbcmask.modify(|_, w| w.$tc().set_bit());
+
+                    let pwm = Pwm {
+                        tc,
+                        clock,
+                        mode,
+                        prescaler,
+                        top,
+                        duty: [0; 2],
+                        enabled: [false; 2],
+                    };
+
+                    {
+                        let count16 = pwm.tc.count16();
+
+                        /* Reset the TC */
+                        count16.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait, the compare values are zero after the reset so both outputs
+                         * stay inactive */
+                        while count16.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    pwm.configure();
+
+                    Ok(pwm)
+                }
+
+                fn configure(&self) {
+                    let count16 = self.tc.count16();
+
+                    /* Stop the timer, CTRLA can only be changed while it's disabled */
+                    count16.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    count16.ctrla.modify(|_, w| {
+                        let w = w.mode().count16().prescaler().bits(self.prescaler);
+                        match self.mode {
+                            Mode::Normal => w.wavegen().npwm(),
+                            Mode::Match => w.wavegen().mpwm(),
+                        }
+                    });
+
+                    /* In MPWM CC0 is the period */
+                    if self.mode == Mode::Match {
+                        count16.cc[0].write(|w| unsafe { w.cc().bits(self.top) });
+
+                        /* And wait */
+                        while count16.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    /* Enable */
+                    count16.ctrla.modify(|_, w| w.enable().set_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                fn write_cc(&self, channel: Channel) {
+                    /* CC0 is the period in MPWM, WO[0] doesn't carry a PWM signal there */
+                    if channel == Channel::Wo0 && self.mode == Mode::Match {
+                        return;
+                    }
+
+                    let index = channel as usize;
+                    let value = if self.enabled[index] { self.duty[index] } else { 0 };
+
+                    let count16 = self.tc.count16();
+                    count16.cc[index].write(|w| unsafe { w.cc().bits(value) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                pub fn set_polarity(&mut self, channel: Channel, polarity: Polarity) {
+                    let count16 = self.tc.count16();
+                    let inverted = polarity == Polarity::Inverted;
+
+                    count16.ctrlc.modify(|_, w| match channel {
+                        Channel::Wo0 => w.inven0().bit(inverted),
+                        Channel::Wo1 => w.inven1().bit(inverted),
+                    });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Drive the channel of `pin` on its own, it starts out disabled */
+                pub fn output<PIN: TcWoPin<$TC>>(&self, pin: PIN) -> PwmOutput<'_, $TC, PIN> {
+                    PwmOutput {
+                        pwm: self,
+                        pin,
+                        duty: 0,
+                        enabled: false,
+                    }
+                }
+
+                /* Keep the PWM running in standby, which needs its generic clock to run in
+                 * standby as well. The TC is briefly disabled and keeps its count */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::tc_run_in_standby(&self.tc, run_in_standby);
+                }
+
+                /* Hand back the TC after disabling it */
+                pub fn free(self, pm: &PM) -> $TC {
+                    let count16 = self.tc.count16();
+
+                    count16.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$tc().clear_bit());
+
+                    self.tc
+                }
+            }
+
+            impl embedded_hal::Pwm for Pwm<$TC> {
+                type Channel = Channel;
+                type Time = Hertz;
+                type Duty = u16;
+
+                fn disable(&mut self, channel: Channel) {
+                    self.enabled[channel as usize] = false;
+                    self.write_cc(channel);
+                }
+
+                fn enable(&mut self, channel: Channel) {
+                    self.enabled[channel as usize] = true;
+                    self.write_cc(channel);
+                }
+
+                fn get_period(&self) -> Hertz {
+                    Hertz(
+                        self.clock.0
+                            / (PRESCALERS[self.prescaler as usize] * (self.top as u32 + 1)),
+                    )
+                }
+
+                fn get_duty(&self, channel: Channel) -> u16 {
+                    self.duty[channel as usize]
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    self.top
+                }
+
+                fn set_duty(&mut self, channel: Channel, duty: u16) {
+                    self.duty[channel as usize] = duty;
+                    self.write_cc(channel);
+                }
+
+                /* In MPWM this changes the maximum duty cycle, so duty cycles need to be set
+                 * again afterwards */
+                fn set_period<P: Into<Hertz>>(&mut self, period: P) {
+                    let (prescaler, top) = prescaler_and_top(self.clock, period.into(), self.mode);
+                    self.prescaler = prescaler;
+                    self.top = top;
+                    self.configure();
+                }
+            }
+
+            impl<'a, PIN: TcWoPin<$TC>> PwmOutput<'a, $TC, PIN> {
+                fn write_cc(&self) {
+                    /* CC0 is the period in MPWM, WO[0] doesn't carry a PWM signal there */
+                    if PIN::WO == 0 && self.pwm.mode == Mode::Match {
+                        return;
+                    }
+
+                    let value = if self.enabled { self.duty } else { 0 };
+
+                    let count16 = self.pwm.tc.count16();
+                    count16.cc[PIN::WO as usize].write(|w| unsafe { w.cc().bits(value) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                pub fn set_polarity(&mut self, polarity: Polarity) {
+                    let count16 = self.pwm.tc.count16();
+                    let inverted = polarity == Polarity::Inverted;
+
+                    count16.ctrlc.modify(|_, w| match PIN::WO {
+                        0 => w.inven0().bit(inverted),
+                        _ => w.inven1().bit(inverted),
+                    });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Hand back the pin, the channel keeps its last duty cycle */
+                pub fn free(self) -> PIN {
+                    self.pin
+                }
+            }
+
+            impl<'a, PIN: TcWoPin<$TC>> embedded_hal::PwmPin for PwmOutput<'a, $TC, PIN> {
+                type Duty = u16;
+
+                fn disable(&mut self) {
+                    self.enabled = false;
+                    self.write_cc();
+                }
+
+                fn enable(&mut self) {
+                    self.enabled = true;
+                    self.write_cc();
+                }
+
+                fn get_duty(&self) -> u16 {
+                    self.duty
+                }
+
+                fn get_max_duty(&self) -> u16 {
+                    self.pwm.top
+                }
+
+                fn set_duty(&mut self, duty: u16) {
+                    self.duty = duty;
+                    self.write_cc();
+                }
+            }
+        )+
+    };
+}
+
+pwm!(
+    TC0: (tc0, Tc0Tc1),
+    TC1: (tc1, Tc0Tc1),
+    TC2: (tc2, Tc2Tc3),
+    TC3: (tc3, Tc2Tc3),
+    TC4: (tc4, Tc4Tc5),
+    TC5: (tc5, Tc4Tc5),
+);
@@ -0,0 +1,70 @@
+use core::ptr;
+
+/* Single producer, single consumer ring buffer on a caller provided static slice, shared between
+ * an interrupt handler and the main code. One slot is kept free to tell full from empty */
+pub(crate) struct RingBuffer<T: Copy> {
+    buf: *mut T,
+    len: usize,
+    head: usize,
+    tail: usize,
+}
+
+impl<T: Copy> RingBuffer<T> {
+    pub const fn empty() -> RingBuffer<T> {
+        RingBuffer {
+            buf: 0 as *mut T,
+            len: 0,
+            head: 0,
+            tail: 0,
+        }
+    }
+
+    pub fn init(&mut self, buf: &'static mut [T]) {
+        self.buf = buf.as_mut_ptr();
+        self.len = buf.len();
+        self.head = 0;
+        self.tail = 0;
+    }
+
+    pub fn is_initialised(&self) -> bool {
+        self.len != 0
+    }
+
+    pub fn is_empty(&self) -> bool {
+        unsafe { ptr::read_volatile(&self.head) == ptr::read_volatile(&self.tail) }
+    }
+
+    /* Called by the producer only */
+    pub fn push(&mut self, value: T) -> Result<(), T> {
+        if self.len == 0 {
+            return Err(value);
+        }
+
+        let head = unsafe { ptr::read_volatile(&self.head) };
+        let next = (head + 1) % self.len;
+        if next == unsafe { ptr::read_volatile(&self.tail) } {
+            return Err(value);
+        }
+
+        unsafe {
+            ptr::write_volatile(self.buf.offset(head as isize), value);
+            ptr::write_volatile(&mut self.head, next);
+        }
+
+        Ok(())
+    }
+
+    /* Called by the consumer only */
+    pub fn pop(&mut self) -> Option<T> {
+        let tail = unsafe { ptr::read_volatile(&self.tail) };
+        if tail == unsafe { ptr::read_volatile(&self.head) } {
+            return None;
+        }
+
+        unsafe {
+            let value = ptr::read_volatile(self.buf.offset(tail as isize));
+            ptr::write_volatile(&mut self.tail, (tail + 1) % self.len);
+            Some(value)
+        }
+    }
+}
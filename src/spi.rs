@@ -0,0 +1,768 @@
+use super::clock::{self, ClockId, Clocks};
+use super::pmux::{Pad0, Pad1, Pad2, Pad3, SercomPad};
+use super::power::{self, WakeSource};
+use super::ringbuf::RingBuffer;
+use super::time::Hertz;
+use super::{Interrupt, NVIC, PM, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
+
+extern crate cortex_m;
+
+use core::marker::PhantomData;
+use cortex_m::interrupt;
+use embedded_hal::blocking;
+use embedded_hal::digital::v2::OutputPin;
+use embedded_hal::spi::{FullDuplex, Phase, Polarity};
+use nb;
+use void::{ResultVoidExt, Void};
+
+pub use embedded_hal::spi::{Mode, MODE_0, MODE_1, MODE_2, MODE_3};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* A character was received before the previous one has been read */
+    Overrun,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum BitOrder {
+    MsbFirst,
+    LsbFirst,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharSize {
+    Eight = 0,
+    Nine = 1,
+}
+
+/* Clock frequency and frame format, SPI mode 0 with 8 bit characters MSB first unless changed.
+ * `WORD` is the type characters are transferred as, `u16` for 9 bit characters and `u8` for 8 bit
+ * ones */
+#[derive(Clone, Copy)]
+pub struct Config<WORD = u8> {
+    freq: Hertz,
+    mode: Mode,
+    bit_order: BitOrder,
+    char_size: CharSize,
+    _word: PhantomData<WORD>,
+}
+
+impl Config<u8> {
+    pub fn new<F: Into<Hertz>>(freq: F) -> Config<u8> {
+        Config {
+            freq: freq.into(),
+            mode: MODE_0,
+            bit_order: BitOrder::MsbFirst,
+            char_size: CharSize::Eight,
+            _word: PhantomData,
+        }
+    }
+
+    pub fn nine_bit(self) -> Config<u16> {
+        Config {
+            freq: self.freq,
+            mode: self.mode,
+            bit_order: self.bit_order,
+            char_size: CharSize::Nine,
+            _word: PhantomData,
+        }
+    }
+}
+
+impl<WORD> Config<WORD> {
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+}
+
+/* Synchronous baud rate setting: BAUD = clock / (2 * freq) - 1, rounded so we never run faster
+ * than asked for. `None` for 0 Hz and for frequencies above clock / 2, which the SERCOM can't
+ * reach */
+fn baud_value(clock: Hertz, freq: Hertz) -> Option<u8> {
+    if freq.0 == 0 || 2 * freq.0 as u64 > clock.0 as u64 {
+        return None;
+    }
+
+    let divider = (clock.0 as u64 + 2 * freq.0 as u64 - 1) / (2 * freq.0 as u64);
+    Some((divider - 1).min(0xFF) as u8)
+}
+
+/* Pads of a master's SCK, MOSI and MISO: MOSI and SCK on one of the pad pairs DOPO supports (0/1,
+ * 2/3, 3/1 or 0/3) and MISO on any other pad. The master leaves slave select to the GPIO layer */
+pub trait MasterPads {
+    const DOPO: u8;
+    const DIPO: u8;
+}
+
+/* Pads of a slave's SCK, MOSI, MISO and SS: MISO and SCK on one of the pad pairs DOPO supports,
+ * SS on the pad that comes with it (2 for 0/1 and 3/1, 1 otherwise) and MOSI on the remaining
+ * one */
+pub trait SlavePads {
+    const DOPO: u8;
+    const DIPO: u8;
+}
+
+macro_rules! master_pads {
+    ($(($SCK:ident, $MOSI:ident, $MISO:ident): ($dopo:expr, $dipo:expr),)+) => {
+        $(
+            impl MasterPads for ($SCK, $MOSI, $MISO) {
+                const DOPO: u8 = $dopo;
+                const DIPO: u8 = $dipo;
+            }
+        )+
+    };
+}
+
+macro_rules! slave_pads {
+    ($(($SCK:ident, $MOSI:ident, $MISO:ident, $SS:ident): ($dopo:expr, $dipo:expr),)+) => {
+        $(
+            impl SlavePads for ($SCK, $MOSI, $MISO, $SS) {
+                const DOPO: u8 = $dopo;
+                const DIPO: u8 = $dipo;
+            }
+        )+
+    };
+}
+
+master_pads!(
+    (Pad1, Pad0, Pad2): (0, 2),
+    (Pad1, Pad0, Pad3): (0, 3),
+    (Pad3, Pad2, Pad0): (1, 0),
+    (Pad3, Pad2, Pad1): (1, 1),
+    (Pad1, Pad3, Pad0): (2, 0),
+    (Pad1, Pad3, Pad2): (2, 2),
+    (Pad3, Pad0, Pad1): (3, 1),
+    (Pad3, Pad0, Pad2): (3, 2),
+);
+
+slave_pads!(
+    (Pad1, Pad3, Pad0, Pad2): (0, 3),
+    (Pad3, Pad0, Pad2, Pad1): (1, 0),
+    (Pad1, Pad0, Pad3, Pad2): (2, 0),
+    (Pad3, Pad2, Pad0, Pad1): (3, 2),
+);
+
+/* SPI master on the pads given by `MasterPads`, transferring `WORD`s as set by its `Config` */
+pub struct Spi<SERCOM, SCK, MOSI, MISO, WORD = u8> {
+    sercom: SERCOM,
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    _word: PhantomData<WORD>,
+}
+
+macro_rules! spi {
+    ($($SERCOM:ident: ($sercom:ident, $id:ident),)+) => {
+        $(
+            impl<SCK, MOSI, MISO, WORD> Spi<$SERCOM, SCK, MOSI, MISO, WORD>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+                /* Take over the SERCOM, which needs a generic clock routed to its core channel */
+                pub fn $sercom(
+                    sercom: $SERCOM,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    config: Config<WORD>,
+                    sck: SCK,
+                    mosi: MOSI,
+                    miso: MISO,
+                ) -> Result<Spi<$SERCOM, SCK, MOSI, MISO, WORD>, clock::Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+                    let baud = baud_value(clock, config.freq)
+                        .ok_or(clock::Error::FrequencyTooLow(ClockId::$id))?;
+
+                    /* Enable clock for the SERCOM */
+                    pm.apbcmask.modify(|_, w| w.$sercom().set_bit());
+
+                    {
+                        let spi = sercom.spi();
+
+                        /* Reset the SERCOM */
+                        spi.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while spi.ctrla.read().swrst().bit_is_set() {}
+                        while spi.status.read().syncbusy().bit_is_set() {}
+
+                        /* Master mode with the requested pads, clock mode and bit order */
+                        spi.ctrla.write(|w| {
+                            let w = w.mode().spi_master().form().spi();
+                            let w = unsafe {
+                                w.dopo()
+                                    .bits(<(SCK::Pad, MOSI::Pad, MISO::Pad)>::DOPO)
+                                    .dipo()
+                                    .bits(<(SCK::Pad, MOSI::Pad, MISO::Pad)>::DIPO)
+                            };
+                            w.cpol()
+                                .bit(config.mode.polarity == Polarity::IdleHigh)
+                                .cpha()
+                                .bit(config.mode.phase == Phase::CaptureOnSecondTransition)
+                                .dord()
+                                .bit(config.bit_order == BitOrder::LsbFirst)
+                        });
+
+                        /* Set character size and enable the receiver */
+                        spi.ctrlb.write(|w| unsafe {
+                            w.chsize().bits(config.char_size as u8).rxen().set_bit()
+                        });
+
+                        /* And wait */
+                        while spi.status.read().syncbusy().bit_is_set() {}
+
+                        spi.baud
+                            .write(|w| unsafe { w.baud().bits(baud) });
+
+                        /* Enable */
+                        spi.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while spi.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(Spi {
+                        sercom,
+                        sck,
+                        mosi,
+                        miso,
+                        _word: PhantomData,
+                    })
+                }
+
+                fn read_word(&mut self) -> nb::Result<u16, Error> {
+                    let spi = self.sercom.spi();
+
+                    if spi.status.read().bufovf().bit_is_set() {
+                        /* Clear the error, it's write-one-to-clear */
+                        spi.status.write(|w| w.bufovf().set_bit());
+                        return Err(nb::Error::Other(Error::Overrun));
+                    }
+
+                    if spi.intflag.read().rxc().bit_is_set() {
+                        Ok(spi.data.read().data().bits())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn send_word(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let spi = self.sercom.spi();
+
+                    if spi.intflag.read().dre().bit_is_set() {
+                        spi.data.write(|w| unsafe { w.data().bits(word) });
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /* Keep the SPI running in standby, which needs its generic clock to run in
+                 * standby as well. The SERCOM is briefly disabled, cutting short a transfer in
+                 * progress */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::sercom_run_in_standby(&self.sercom, run_in_standby);
+                }
+
+                /* Hand back SERCOM and pins after disabling the SPI */
+                pub fn free(self, pm: &PM) -> ($SERCOM, SCK, MOSI, MISO) {
+                    let spi = self.sercom.spi();
+
+                    spi.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while spi.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$sercom().clear_bit());
+
+                    (self.sercom, self.sck, self.mosi, self.miso)
+                }
+            }
+
+            impl<SCK, MOSI, MISO> FullDuplex<u8> for Spi<$SERCOM, SCK, MOSI, MISO, u8>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.read_word().map(|word| word as u8)
+                }
+
+                fn send(&mut self, word: u8) -> nb::Result<(), Error> {
+                    self.send_word(word as u16)
+                }
+            }
+
+            impl<SCK, MOSI, MISO> FullDuplex<u16> for Spi<$SERCOM, SCK, MOSI, MISO, u16>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    self.read_word()
+                }
+
+                fn send(&mut self, word: u16) -> nb::Result<(), Error> {
+                    self.send_word(word)
+                }
+            }
+
+            impl<SCK, MOSI, MISO> blocking::spi::transfer::Default<u8>
+                for Spi<$SERCOM, SCK, MOSI, MISO, u8>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+            }
+
+            impl<SCK, MOSI, MISO> blocking::spi::write::Default<u8>
+                for Spi<$SERCOM, SCK, MOSI, MISO, u8>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+            }
+
+            impl<SCK, MOSI, MISO> blocking::spi::transfer::Default<u16>
+                for Spi<$SERCOM, SCK, MOSI, MISO, u16>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+            }
+
+            impl<SCK, MOSI, MISO> blocking::spi::write::Default<u16>
+                for Spi<$SERCOM, SCK, MOSI, MISO, u16>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad): MasterPads,
+            {
+            }
+        )+
+    };
+}
+
+spi!(
+    SERCOM0: (sercom0, Sercom0Core),
+    SERCOM1: (sercom1, Sercom1Core),
+    SERCOM2: (sercom2, Sercom2Core),
+    SERCOM3: (sercom3, Sercom3Core),
+);
+
+/* Active low chip select on any output pin, deselected on creation. Setting our pins can't fail,
+ * so neither can selecting */
+pub struct ChipSelect<PIN> {
+    pin: PIN,
+}
+
+impl<PIN: OutputPin<Error = Void>> ChipSelect<PIN> {
+    pub fn new(mut pin: PIN) -> ChipSelect<PIN> {
+        pin.set_high().void_unwrap();
+        ChipSelect { pin }
+    }
+
+    pub fn select(&mut self) {
+        self.pin.set_low().void_unwrap();
+    }
+
+    pub fn deselect(&mut self) {
+        self.pin.set_high().void_unwrap();
+    }
+
+    /* Run `f` with the device selected, e.g. for one command of an SPI flash */
+    pub fn transaction<R, F: FnOnce() -> R>(&mut self, f: F) -> R {
+        self.select();
+        let result = f();
+        self.deselect();
+        result
+    }
+
+    pub fn free(self) -> PIN {
+        self.pin
+    }
+}
+
+/* Which addresses a slave answers to, compared against the first character of each transaction */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum AddressMatch {
+    /* Match `address` on the bits set in `mask` */
+    Mask { address: u8, mask: u8 },
+    /* Match either of two addresses */
+    TwoAddresses(u8, u8),
+    /* Match all addresses from `low` to `high` inclusive */
+    Range { low: u8, high: u8 },
+}
+
+/* Frame format and address recognition of a slave, SPI mode 0 with 8 bit characters MSB first and
+ * no address recognition unless changed */
+#[derive(Clone, Copy)]
+pub struct SlaveConfig {
+    mode: Mode,
+    bit_order: BitOrder,
+    char_size: CharSize,
+    address: Option<AddressMatch>,
+}
+
+impl SlaveConfig {
+    pub fn new() -> SlaveConfig {
+        SlaveConfig {
+            mode: MODE_0,
+            bit_order: BitOrder::MsbFirst,
+            char_size: CharSize::Eight,
+            address: None,
+        }
+    }
+
+    pub fn mode(mut self, mode: Mode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    pub fn bit_order(mut self, bit_order: BitOrder) -> Self {
+        self.bit_order = bit_order;
+        self
+    }
+
+    pub fn char_size(mut self, char_size: CharSize) -> Self {
+        self.char_size = char_size;
+        self
+    }
+
+    /* Only take part in transactions starting with a matching address. The address is received
+     * like any other character */
+    pub fn address(mut self, address: AddressMatch) -> Self {
+        self.address = Some(address);
+        self
+    }
+}
+
+/* Receive and transmit buffers of SERCOM0-3 in interrupt-driven slave mode, receive overruns the
+ * interrupt handler has seen since they were last reported, the slave select callbacks and
+ * whether a transaction is running */
+static mut SLAVE_RX_BUFFERS: [RingBuffer<u16>; 4] = [
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+];
+static mut SLAVE_TX_BUFFERS: [RingBuffer<u16>; 4] = [
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+];
+static mut SLAVE_OVERRUNS: [bool; 4] = [false; 4];
+static mut SLAVE_SELECT_CALLBACKS: [Option<fn()>; 4] = [None; 4];
+static mut SLAVE_SELECTED: [bool; 4] = [false; 4];
+
+/* SPI slave with preloading on the pads given by `SlavePads` */
+pub struct SpiSlave<SERCOM, SCK, MOSI, MISO, SS> {
+    sercom: SERCOM,
+    sck: SCK,
+    mosi: MOSI,
+    miso: MISO,
+    ss: SS,
+}
+
+macro_rules! spi_slave {
+    ($($SERCOM:ident: ($sercom:ident, $n:expr, $id:ident, $handle_interrupt:ident),)+) => {
+        $(
+            impl<SCK, MOSI, MISO, SS> SpiSlave<$SERCOM, SCK, MOSI, MISO, SS>
+            where
+                SCK: SercomPad<$SERCOM>,
+                MOSI: SercomPad<$SERCOM>,
+                MISO: SercomPad<$SERCOM>,
+                SS: SercomPad<$SERCOM>,
+                (SCK::Pad, MOSI::Pad, MISO::Pad, SS::Pad): SlavePads,
+            {
+                /* Take over the SERCOM, which needs a generic clock routed to its core channel
+                 * for the register interface only, SCK comes from the master */
+                pub fn $sercom(
+                    sercom: $SERCOM,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    config: SlaveConfig,
+                    sck: SCK,
+                    mosi: MOSI,
+                    miso: MISO,
+                    ss: SS,
+                ) -> Result<SpiSlave<$SERCOM, SCK, MOSI, MISO, SS>, clock::Error> {
+                    clocks.require(ClockId::$id)?;
+
+                    /* Enable clock for the SERCOM */
+                    pm.apbcmask.modify(|_, w| w.$sercom().set_bit());
+
+                    {
+                        let spi = sercom.spi();
+
+                        /* Reset the SERCOM */
+                        spi.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while spi.ctrla.read().swrst().bit_is_set() {}
+                        while spi.status.read().syncbusy().bit_is_set() {}
+
+                        /* Slave mode with the requested pads, clock mode, bit order and frame */
+                        spi.ctrla.write(|w| {
+                            let w = w.mode().spi_slave();
+                            let w = match config.address {
+                                Some(_) => w.form().spi_addr(),
+                                None => w.form().spi(),
+                            };
+                            let w = unsafe {
+                                w.dopo()
+                                    .bits(<(SCK::Pad, MOSI::Pad, MISO::Pad, SS::Pad)>::DOPO)
+                                    .dipo()
+                                    .bits(<(SCK::Pad, MOSI::Pad, MISO::Pad, SS::Pad)>::DIPO)
+                            };
+                            w.cpol()
+                                .bit(config.mode.polarity == Polarity::IdleHigh)
+                                .cpha()
+                                .bit(config.mode.phase == Phase::CaptureOnSecondTransition)
+                                .dord()
+                                .bit(config.bit_order == BitOrder::LsbFirst)
+                        });
+
+                        /* Set character size and address mode, and enable preloading and the
+                         * receiver */
+                        spi.ctrlb.write(|w| {
+                            let w = unsafe { w.chsize().bits(config.char_size as u8) };
+                            let w = w.ploaden().set_bit().rxen().set_bit();
+                            match config.address {
+                                Some(AddressMatch::TwoAddresses(_, _)) => w.amode()._2addr(),
+                                Some(AddressMatch::Range { .. }) => w.amode().range(),
+                                _ => w.amode().mask(),
+                            }
+                        });
+
+                        /* And wait */
+                        while spi.status.read().syncbusy().bit_is_set() {}
+
+                        /* ADDRMASK has the bits to ignore, for ranges ADDR is the upper and
+                         * ADDRMASK the lower limit */
+                        let (addr, addrmask) = match config.address {
+                            Some(AddressMatch::Mask { address, mask }) => (address, !mask),
+                            Some(AddressMatch::TwoAddresses(first, second)) => (first, second),
+                            Some(AddressMatch::Range { low, high }) => (high, low),
+                            None => (0, 0),
+                        };
+                        spi.addr
+                            .write(|w| unsafe { w.addr().bits(addr).addrmask().bits(addrmask) });
+
+                        /* Enable */
+                        spi.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while spi.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(SpiSlave {
+                        sercom,
+                        sck,
+                        mosi,
+                        miso,
+                        ss,
+                    })
+                }
+
+                /* Switch to interrupt-driven mode: received characters are collected in `rx`
+                 * and characters to answer with are preloaded from `tx` by
+                 * `handle_slave_interrupt_sercomN`, one slot of each stays unused. `on_select` is
+                 * called when the master starts a transaction with us. The SAMD20 has no
+                 * interrupt for SS going low, so that's noticed with the first character
+                 * received; SS going high is seen through TXC */
+                pub fn listen(
+                    &mut self,
+                    rx: &'static mut [u16],
+                    tx: &'static mut [u16],
+                    on_select: Option<fn()>,
+                ) {
+                    let spi = self.sercom.spi();
+
+                    interrupt::free(move |cs| {
+                        let nvic = NVIC.borrow(cs);
+
+                        unsafe {
+                            SLAVE_RX_BUFFERS[$n].init(rx);
+                            SLAVE_TX_BUFFERS[$n].init(tx);
+                            SLAVE_OVERRUNS[$n] = false;
+                            SLAVE_SELECT_CALLBACKS[$n] = on_select;
+                            SLAVE_SELECTED[$n] = false;
+                        }
+
+                        /* Receive and end of transaction interrupts are always on, DRE only
+                         * while there's data to preload */
+                        spi.intenset.write(|w| w.rxc().set_bit().txc().set_bit());
+
+                        /* Enable SERCOM IRQs */
+                        nvic.enable(Interrupt::$SERCOM);
+                    });
+                }
+
+                /* Whether the master is in a transaction with us, only tracked in
+                 * interrupt-driven mode */
+                pub fn is_selected(&self) -> bool {
+                    unsafe { SLAVE_SELECTED[$n] }
+                }
+
+                /* Next character received from the master */
+                pub fn read(&mut self) -> nb::Result<u16, Error> {
+                    let spi = self.sercom.spi();
+
+                    if unsafe { SLAVE_RX_BUFFERS[$n].is_initialised() } {
+                        let overrun = interrupt::free(|_| unsafe {
+                            let overrun = SLAVE_OVERRUNS[$n];
+                            SLAVE_OVERRUNS[$n] = false;
+                            overrun
+                        });
+
+                        if overrun {
+                            return Err(nb::Error::Other(Error::Overrun));
+                        }
+
+                        return unsafe { SLAVE_RX_BUFFERS[$n].pop() }.ok_or(nb::Error::WouldBlock);
+                    }
+
+                    if spi.status.read().bufovf().bit_is_set() {
+                        /* Clear the error, it's write-one-to-clear */
+                        spi.status.write(|w| w.bufovf().set_bit());
+                        return Err(nb::Error::Other(Error::Overrun));
+                    }
+
+                    if spi.intflag.read().rxc().bit_is_set() {
+                        Ok(spi.data.read().data().bits())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /* Queue a character to send with the next one the master clocks in. Written
+                 * while SS is high it's preloaded and goes out with the first character */
+                pub fn write(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let spi = self.sercom.spi();
+
+                    if unsafe { SLAVE_TX_BUFFERS[$n].is_initialised() } {
+                        unsafe { SLAVE_TX_BUFFERS[$n].push(word) }
+                            .map_err(|_| nb::Error::WouldBlock)?;
+
+                        /* Have the handler pick it up */
+                        spi.intenset.write(|w| w.dre().set_bit());
+                        return Ok(());
+                    }
+
+                    if spi.intflag.read().dre().bit_is_set() {
+                        spi.data.write(|w| unsafe { w.data().bits(word) });
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                /* Keep the SPI slave running in standby, which needs its generic clock to run in
+                 * standby as well. The SERCOM is briefly disabled, cutting short a transfer in
+                 * progress */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::sercom_run_in_standby(&self.sercom, run_in_standby);
+                }
+
+                /* Hand back SERCOM and pins after disabling the SPI */
+                pub fn free(self, pm: &PM) -> ($SERCOM, SCK, MOSI, MISO, SS) {
+                    let spi = self.sercom.spi();
+
+                    interrupt::free(|_| unsafe {
+                        spi.intenclr.write(|w| w.bits(0xFF));
+                        SLAVE_RX_BUFFERS[$n] = RingBuffer::empty();
+                        SLAVE_TX_BUFFERS[$n] = RingBuffer::empty();
+                        SLAVE_SELECT_CALLBACKS[$n] = None;
+                    });
+
+                    spi.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while spi.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$sercom().clear_bit());
+
+                    (self.sercom, self.sck, self.mosi, self.miso, self.ss)
+                }
+            }
+
+            /* To be called from the interrupt handler of the SERCOM */
+            pub fn $handle_interrupt() {
+                let spi = unsafe { (*$SERCOM::ptr()).spi() };
+                let flags = spi.intflag.read();
+
+                if flags.rxc().bit_is_set() {
+                    if unsafe { !SLAVE_SELECTED[$n] } {
+                        unsafe { SLAVE_SELECTED[$n] = true };
+                        if let Some(callback) = unsafe { SLAVE_SELECT_CALLBACKS[$n] } {
+                            callback();
+                        }
+                    }
+
+                    if spi.status.read().bufovf().bit_is_set() {
+                        spi.status.write(|w| w.bufovf().set_bit());
+                        unsafe { SLAVE_OVERRUNS[$n] = true };
+                    }
+
+                    let data = spi.data.read().data().bits();
+                    if unsafe { SLAVE_RX_BUFFERS[$n].push(data) }.is_err() {
+                        unsafe { SLAVE_OVERRUNS[$n] = true };
+                    }
+                }
+
+                /* In slave mode TXC means SS went high */
+                if flags.txc().bit_is_set() {
+                    spi.intflag.write(|w| w.txc().set_bit());
+                    unsafe { SLAVE_SELECTED[$n] = false };
+                }
+
+                if flags.dre().bit_is_set() && spi.intenset.read().dre().bit_is_set() {
+                    match unsafe { SLAVE_TX_BUFFERS[$n].pop() } {
+                        Some(data) => spi.data.write(|w| unsafe { w.data().bits(data) }),
+                        None => spi.intenclr.write(|w| w.dre().set_bit()),
+                    }
+                }
+            }
+
+            impl<SCK, MOSI, MISO, SS> WakeSource for SpiSlave<$SERCOM, SCK, MOSI, MISO, SS> {
+                fn is_armed(&self, pm: &PM) -> bool {
+                    power::bus_clock_enabled(pm, &self.sercom)
+                        && power::nvic_enabled(Interrupt::$SERCOM)
+                        && self.sercom.spi().intenset.read().bits() != 0
+                }
+            }
+        )+
+    };
+}
+
+spi_slave!(
+    SERCOM0: (sercom0, 0, Sercom0Core, handle_slave_interrupt_sercom0),
+    SERCOM1: (sercom1, 1, Sercom1Core, handle_slave_interrupt_sercom1),
+    SERCOM2: (sercom2, 2, Sercom2Core, handle_slave_interrupt_sercom2),
+    SERCOM3: (sercom3, 3, Sercom3Core, handle_slave_interrupt_sercom3),
+);
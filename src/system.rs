@@ -0,0 +1,137 @@
+use super::{DSU, PM};
+
+use core::ptr;
+
+/* Addresses of the four words of the serial number, from most to least significant */
+const SERIAL_NUMBER_WORDS: [u32; 4] = [0x0080_A00C, 0x0080_A040, 0x0080_A044, 0x0080_A048];
+
+/* Pin count and flash size in KiB for each DEVSEL of the SAMD20 */
+const DEVICES: [(u8, u16); 15] = [
+    (64, 256),
+    (64, 128),
+    (64, 64),
+    (64, 32),
+    (64, 16),
+    (48, 256),
+    (48, 128),
+    (48, 64),
+    (48, 32),
+    (48, 16),
+    (32, 256),
+    (32, 128),
+    (32, 64),
+    (32, 32),
+    (32, 16),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ResetCause {
+    PowerOn,
+    /* Brown-out of the core supply */
+    Bod12,
+    /* Brown-out of the I/O supply */
+    Bod33,
+    /* RESET pin */
+    External,
+    Watchdog,
+    /* Requested by software through the AIRCR of the SCB */
+    System,
+}
+
+/* Cause of the last reset, `None` if PM.RCAUSE has none set */
+pub fn reset_cause(pm: &PM) -> Option<ResetCause> {
+    let rcause = pm.rcause.read();
+
+    /* A power-on reset may flag the brown-out detectors too */
+    if rcause.por().bit_is_set() {
+        Some(ResetCause::PowerOn)
+    } else if rcause.bod12().bit_is_set() {
+        Some(ResetCause::Bod12)
+    } else if rcause.bod33().bit_is_set() {
+        Some(ResetCause::Bod33)
+    } else if rcause.ext().bit_is_set() {
+        Some(ResetCause::External)
+    } else if rcause.wdt().bit_is_set() {
+        Some(ResetCause::Watchdog)
+    } else if rcause.syst().bit_is_set() {
+        Some(ResetCause::System)
+    } else {
+        None
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Processor {
+    CortexM0Plus,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Family {
+    /* General purpose microcontroller */
+    D,
+    Unknown(u8),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Series {
+    /* Cortex-M0+ processor, basic feature set */
+    Samd20,
+    Unknown(u8),
+}
+
+/* Contents of DSU.DID */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct DeviceId {
+    pub processor: Processor,
+    pub family: Family,
+    pub series: Series,
+    pub die: u8,
+    pub revision: u8,
+    pub devsel: u8,
+}
+
+impl DeviceId {
+    pub fn read(dsu: &DSU) -> DeviceId {
+        let did = dsu.did.read();
+
+        DeviceId {
+            processor: match did.processor().bits() {
+                1 => Processor::CortexM0Plus,
+                n => Processor::Unknown(n),
+            },
+            family: match did.family().bits() {
+                0 => Family::D,
+                n => Family::Unknown(n),
+            },
+            series: match did.series().bits() {
+                0 => Series::Samd20,
+                n => Series::Unknown(n),
+            },
+            die: did.die().bits(),
+            revision: did.revision().bits(),
+            devsel: did.devsel().bits(),
+        }
+    }
+
+    /* Revision as printed in the datasheet, 0 being 'A' */
+    pub fn revision_letter(&self) -> char {
+        (b'A' + (self.revision & 0xF)) as char
+    }
+
+    /* Pin count and flash size in KiB, `None` for a DEVSEL unknown to the SAMD20 */
+    pub fn variant(&self) -> Option<(u8, u16)> {
+        DEVICES.get(self.devsel as usize).cloned()
+    }
+}
+
+/* 128 bit serial number unique to each device, most significant word first */
+pub fn serial_number() -> [u32; 4] {
+    let mut serial = [0; 4];
+
+    for (word, &address) in serial.iter_mut().zip(SERIAL_NUMBER_WORDS.iter()) {
+        *word = unsafe { ptr::read_volatile(address as *const u32) };
+    }
+
+    serial
+}
@@ -0,0 +1,56 @@
+/* Units of frequency, e.g. `48.mhz()` for use with the clock configuration, and of time */
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct Hertz(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct KiloHertz(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct MegaHertz(pub u32);
+
+#[derive(Clone, Copy, Debug, PartialEq, PartialOrd)]
+pub struct MilliSeconds(pub u32);
+
+pub trait U32Ext {
+    fn hz(self) -> Hertz;
+    fn khz(self) -> KiloHertz;
+    fn mhz(self) -> MegaHertz;
+    fn ms(self) -> MilliSeconds;
+}
+
+impl U32Ext for u32 {
+    fn hz(self) -> Hertz {
+        Hertz(self)
+    }
+
+    fn khz(self) -> KiloHertz {
+        KiloHertz(self)
+    }
+
+    fn mhz(self) -> MegaHertz {
+        MegaHertz(self)
+    }
+
+    fn ms(self) -> MilliSeconds {
+        MilliSeconds(self)
+    }
+}
+
+impl From<KiloHertz> for Hertz {
+    fn from(val: KiloHertz) -> Hertz {
+        Hertz(val.0 * 1_000)
+    }
+}
+
+impl From<MegaHertz> for Hertz {
+    fn from(val: MegaHertz) -> Hertz {
+        Hertz(val.0 * 1_000_000)
+    }
+}
+
+impl From<MegaHertz> for KiloHertz {
+    fn from(val: MegaHertz) -> KiloHertz {
+        KiloHertz(val.0 * 1_000)
+    }
+}
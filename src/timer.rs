@@ -0,0 +1,282 @@
+use super::clock::{ClockId, Clocks, Error};
+use super::power;
+use super::time::Hertz;
+use super::{Interrupt, NVIC, PM, TC0, TC1, TC2, TC3, TC4, TC5};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use embedded_hal::timer::{CountDown, Periodic};
+use nb;
+use void::Void;
+
+/* Available prescalers as selectable in CTRLA.PRESCALER */
+pub(crate) const PRESCALERS: [u32; 8] = [1, 2, 4, 8, 16, 64, 256, 1024];
+
+/* Address of the COUNT register for read synchronisation */
+pub(crate) const COUNT16_COUNT_ADDR: u8 = 0x10;
+
+/* Interrupts a timer can raise, numbered by their callback slot */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Event {
+    Overflow = 0,
+    Compare0,
+    Compare1,
+}
+
+/* Bits of the events in INTENSET/INTENCLR/INTFLAG */
+const EVENT_BITS: [u8; 3] = [1 << 0, 1 << 4, 1 << 5];
+
+/* Bits of the events in EVCTRL */
+const EVENT_OUTPUT_BITS: [u16; 3] = [1 << 8, 1 << 12, 1 << 13];
+
+/* Callbacks for each event of TC0-TC5, run by `handle_interrupt` */
+static mut CALLBACKS: [[Option<fn()>; 3]; 6] = [[None; 3]; 6];
+
+/* Pick the smallest prescaler which makes `ticks` fit into a 16 bit period, periods too long even
+ * for the largest prescaler are clamped to the longest one possible */
+pub(crate) fn prescaler_and_period(ticks: u32) -> (u8, u16) {
+    for (i, prescaler) in PRESCALERS.iter().enumerate() {
+        let period = ticks / prescaler;
+        if period <= 0x1_0000 {
+            return (i as u8, (period.max(2) - 1) as u16);
+        }
+    }
+
+    (PRESCALERS.len() as u8 - 1, 0xFFFF)
+}
+
+/* 16 bit timer counting up to a period derived from the requested frequency, CC0 holds the
+ * period (MFRQ) so CC1 is left for compare matches */
+pub struct Timer<TC> {
+    tc: TC,
+    clock: Hertz,
+}
+
+macro_rules! timers {
+    ($($TC:ident: ($tc:ident, $n:expr, $id:ident),)+) => {
+        $(
+            impl Timer<$TC> {
+                /* Take over the timer, which needs a generic clock routed to the channel of its pair */
+                pub fn $tc(tc: $TC, pm: &PM, clocks: &Clocks) -> Result<Timer<$TC>, Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+
+                    /* Enable clock for the TC */
+                    pm.apbcmask.modify(|_, w| w.$tc().set_bit());
+
+                    {
+                        let count16 = tc.count16();
+
+                        /* Reset the TC */
+                        count16.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while count16.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(Timer { tc, clock })
+                }
+
+                /* Run once and stop at the next overflow instead of wrapping around */
+                pub fn set_one_shot(&mut self, one_shot: bool) {
+                    let count16 = self.tc.count16();
+
+                    if one_shot {
+                        count16.ctrlbset.write(|w| w.oneshot().set_bit());
+                    } else {
+                        count16.ctrlbclr.write(|w| w.oneshot().set_bit());
+                    }
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Restart counting from zero, also starts a one-shot timer which has stopped */
+                pub fn retrigger(&mut self) {
+                    let count16 = self.tc.count16();
+
+                    count16.ctrlbset.write(|w| w.cmd().retrigger());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Set the value for the CC1 compare match within the period */
+                pub fn set_compare(&mut self, value: u16) {
+                    let count16 = self.tc.count16();
+
+                    count16.cc[1].write(|w| unsafe { w.cc().bits(value) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                pub fn count(&self) -> u16 {
+                    let count16 = self.tc.count16();
+
+                    /* Request a synchronised read of COUNT */
+                    count16
+                        .readreq
+                        .write(|w| unsafe { w.addr().bits(COUNT16_COUNT_ADDR) }.rreq().set_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    count16.count.read().count().bits()
+                }
+
+                /* Send an event to the EVSYS whenever `event` happens, e.g. to trigger the DAC */
+                pub fn set_event_output(&mut self, event: Event, enable: bool) {
+                    let bit = EVENT_OUTPUT_BITS[event as usize];
+
+                    self.tc.count16().evctrl.modify(|r, w| unsafe {
+                        w.bits(if enable {
+                            r.bits() | bit
+                        } else {
+                            r.bits() & !bit
+                        })
+                    });
+                }
+
+                /* Call `callback` from `handle_interrupt` whenever `event` happens */
+                pub fn listen(&mut self, event: Event, callback: fn()) {
+                    let count16 = self.tc.count16();
+                    let bit = EVENT_BITS[event as usize];
+
+                    /* Make sure the handler doesn't run while we swap the callback */
+                    count16.intenclr.write(|w| unsafe { w.bits(bit) });
+                    unsafe { CALLBACKS[$n][event as usize] = Some(callback) };
+
+                    /* Clear any stale interrupt and enable it */
+                    count16.intflag.write(|w| unsafe { w.bits(bit) });
+                    count16.intenset.write(|w| unsafe { w.bits(bit) });
+
+                    interrupt::free(|cs| {
+                        let nvic = NVIC.borrow(cs);
+
+                        /* Enable TC IRQs */
+                        nvic.enable(Interrupt::$TC);
+                    });
+                }
+
+                pub fn unlisten(&mut self, event: Event) {
+                    let bit = EVENT_BITS[event as usize];
+
+                    self.tc.count16().intenclr.write(|w| unsafe { w.bits(bit) });
+                    unsafe { CALLBACKS[$n][event as usize] = None };
+                }
+
+                /* To be called from the interrupt handler of the TC: clears the flag of every
+                 * pending event and runs its callback */
+                pub fn handle_interrupt() {
+                    let count16 = unsafe { (*$TC::ptr()).count16() };
+
+                    let pending = count16.intflag.read().bits() & count16.intenset.read().bits();
+                    count16.intflag.write(|w| unsafe { w.bits(pending) });
+
+                    for (event, bit) in EVENT_BITS.iter().enumerate() {
+                        if pending & bit != 0 {
+                            if let Some(callback) = unsafe { CALLBACKS[$n][event] } {
+                                callback();
+                            }
+                        }
+                    }
+                }
+
+                /* Keep the timer running in standby, which needs its generic clock to run in
+                 * standby as well. The TC is briefly disabled and keeps its count */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::tc_run_in_standby(&self.tc, run_in_standby);
+                }
+
+                /* Hand back the TC after disabling it */
+                pub fn free(self, pm: &PM) -> $TC {
+                    let count16 = self.tc.count16();
+
+                    count16.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$tc().clear_bit());
+
+                    self.tc
+                }
+            }
+
+            impl CountDown for Timer<$TC> {
+                type Time = Hertz;
+
+                /* 0 Hz is taken as 1 Hz, giving the longest period */
+                fn start<T: Into<Hertz>>(&mut self, freq: T) {
+                    let count16 = self.tc.count16();
+                    let ticks = self.clock.0 / freq.into().0.max(1);
+                    let (prescaler, period) = prescaler_and_period(ticks);
+
+                    /* Stop the timer, CTRLA can only be changed while it's disabled */
+                    count16.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    /* Count up to CC0, restarting the prescaler along with the counter */
+                    count16.ctrla.modify(|_, w| {
+                        w.mode()
+                            .count16()
+                            .wavegen()
+                            .mfrq()
+                            .prescaler()
+                            .bits(prescaler)
+                            .prescsync()
+                            .presc()
+                    });
+
+                    /* Start from the beginning */
+                    count16.count.write(|w| unsafe { w.count().bits(0) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    /* Setup period */
+                    count16.cc[0].write(|w| unsafe { w.cc().bits(period) });
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+
+                    /* Forget about overflows of the previous run */
+                    count16.intflag.write(|w| w.ovf().set_bit());
+
+                    /* Enable */
+                    count16.ctrla.modify(|_, w| w.enable().set_bit());
+
+                    /* And wait */
+                    while count16.status.read().syncbusy().bit_is_set() {}
+                }
+
+                /* Note that this never returns if the overflow interrupt is handled by a
+                 * callback since `handle_interrupt` clears the flag */
+                fn wait(&mut self) -> nb::Result<(), Void> {
+                    let count16 = self.tc.count16();
+
+                    if count16.intflag.read().ovf().bit_is_set() {
+                        count16.intflag.write(|w| w.ovf().set_bit());
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+            }
+
+            impl Periodic for Timer<$TC> {}
+        )+
+    };
+}
+
+timers!(
+    TC0: (tc0, 0, Tc0Tc1),
+    TC1: (tc1, 1, Tc0Tc1),
+    TC2: (tc2, 2, Tc2Tc3),
+    TC3: (tc3, 3, Tc2Tc3),
+    TC4: (tc4, 4, Tc4Tc5),
+    TC5: (tc5, 5, Tc4Tc5),
+);
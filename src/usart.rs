@@ -0,0 +1,507 @@
+use super::clock::{self, ClockId, Clocks};
+use super::pmux::{Pad0, Pad1, Pad2, Pad3, SercomPad};
+use super::power::{self, WakeSource};
+use super::ringbuf::RingBuffer;
+use super::time::Hertz;
+use super::{Interrupt, NVIC, PM, SERCOM0, SERCOM1, SERCOM2, SERCOM3};
+
+extern crate cortex_m;
+
+use core::marker::PhantomData;
+use cortex_m::interrupt;
+use embedded_hal::blocking;
+use embedded_hal::serial::{Read, Write};
+use nb;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Error {
+    /* A stop bit was missing */
+    Framing,
+    /* The parity bit didn't match */
+    Parity,
+    /* A character was lost because the receive buffer was full */
+    BufferOverflow,
+}
+
+/* Bits of the errors in STATUS, also used to remember errors seen by the interrupt handler */
+const STATUS_PERR: u16 = 1 << 0;
+const STATUS_FERR: u16 = 1 << 1;
+const STATUS_BUFOVF: u16 = 1 << 2;
+
+fn error_from_status(status: u16) -> Option<Error> {
+    if status & STATUS_FERR != 0 {
+        Some(Error::Framing)
+    } else if status & STATUS_PERR != 0 {
+        Some(Error::Parity)
+    } else if status & STATUS_BUFOVF != 0 {
+        Some(Error::BufferOverflow)
+    } else {
+        None
+    }
+}
+
+/* Character sizes carried in a `u8`, 9 bit characters are set up with `Config::nine_bit` */
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharSize {
+    Five = 5,
+    Six = 6,
+    Seven = 7,
+    Eight = 0,
+}
+
+/* CHSIZE setting for 9 bit characters */
+const CHSIZE_NINE: u8 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Parity {
+    None,
+    Even,
+    Odd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum StopBits {
+    One,
+    Two,
+}
+
+/* Frame format and baud rate, 8N1 unless changed. `WORD` is the type characters are read and
+ * written as, `u16` for 9 bit characters and `u8` for all others */
+#[derive(Clone, Copy, Debug)]
+pub struct Config<WORD = u8> {
+    baud: Hertz,
+    chsize: u8,
+    parity: Parity,
+    stop_bits: StopBits,
+    _word: PhantomData<WORD>,
+}
+
+impl Config<u8> {
+    pub fn new<B: Into<Hertz>>(baud: B) -> Config<u8> {
+        Config {
+            baud: baud.into(),
+            chsize: CharSize::Eight as u8,
+            parity: Parity::None,
+            stop_bits: StopBits::One,
+            _word: PhantomData,
+        }
+    }
+
+    pub fn char_size(mut self, char_size: CharSize) -> Self {
+        self.chsize = char_size as u8;
+        self
+    }
+
+    pub fn nine_bit(self) -> Config<u16> {
+        Config {
+            baud: self.baud,
+            chsize: CHSIZE_NINE,
+            parity: self.parity,
+            stop_bits: self.stop_bits,
+            _word: PhantomData,
+        }
+    }
+}
+
+impl<WORD> Config<WORD> {
+    pub fn parity(mut self, parity: Parity) -> Self {
+        self.parity = parity;
+        self
+    }
+
+    pub fn stop_bits(mut self, stop_bits: StopBits) -> Self {
+        self.stop_bits = stop_bits;
+        self
+    }
+}
+
+/* Arithmetic baud rate setting for 16x oversampling: BAUD = 65536 * (1 - 16 * baud / clock),
+ * `None` if the clock is too slow for `baud` */
+fn baud_value(clock: Hertz, baud: Hertz) -> Option<u16> {
+    if 16 * baud.0 as u64 > clock.0 as u64 {
+        return None;
+    }
+
+    let ratio = ((16 * baud.0 as u64) << 16) / clock.0 as u64;
+    /* 0 baud would need BAUD = 65536, take the slowest rate instead */
+    Some((65536 - ratio).min(0xFFFF) as u16)
+}
+
+/* Receive and transmit buffers of SERCOM0-3 in interrupt-driven mode and the receive errors the
+ * interrupt handler has seen since they were last reported */
+static mut RX_BUFFERS: [RingBuffer<u16>; 4] = [
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+];
+static mut TX_BUFFERS: [RingBuffer<u16>; 4] = [
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+    RingBuffer::empty(),
+];
+static mut RX_ERRORS: [u16; 4] = [0; 4];
+
+/* Pads of TX and RX, TX may sit on PAD0 or PAD2 and RX on any other pad */
+pub trait UsartPads {
+    const TXPO: bool;
+    const RXPO: u8;
+}
+
+macro_rules! usart_pads {
+    ($(($TX:ident, $RX:ident): ($txpo:expr, $rxpo:expr),)+) => {
+        $(
+            impl UsartPads for ($TX, $RX) {
+                const TXPO: bool = $txpo;
+                const RXPO: u8 = $rxpo;
+            }
+        )+
+    };
+}
+
+usart_pads!(
+    (Pad0, Pad1): (false, 1),
+    (Pad0, Pad2): (false, 2),
+    (Pad0, Pad3): (false, 3),
+    (Pad2, Pad0): (true, 0),
+    (Pad2, Pad1): (true, 1),
+    (Pad2, Pad3): (true, 3),
+);
+
+/* USART with internal clock and 16x oversampling, characters are read and written as `WORD` */
+pub struct Usart<SERCOM, TX, RX, WORD = u8> {
+    sercom: SERCOM,
+    tx: TX,
+    rx: RX,
+    /* Written characters might not have been sent completely yet */
+    pending: bool,
+    _word: PhantomData<WORD>,
+}
+
+macro_rules! usart {
+    ($($SERCOM:ident: ($sercom:ident, $n:expr, $id:ident, $handle_interrupt:ident),)+) => {
+        $(
+            impl<TX, RX, WORD> Usart<$SERCOM, TX, RX, WORD>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+                /* Take over the SERCOM, which needs a generic clock routed to its core channel */
+                pub fn $sercom(
+                    sercom: $SERCOM,
+                    pm: &PM,
+                    clocks: &Clocks,
+                    config: Config<WORD>,
+                    tx: TX,
+                    rx: RX,
+                ) -> Result<Usart<$SERCOM, TX, RX, WORD>, clock::Error> {
+                    let clock = clocks.require(ClockId::$id)?;
+                    let baud = baud_value(clock, config.baud)
+                        .ok_or(clock::Error::FrequencyTooLow(ClockId::$id))?;
+
+                    /* Enable clock for the SERCOM */
+                    pm.apbcmask.modify(|_, w| w.$sercom().set_bit());
+
+                    {
+                        let usart = sercom.usart();
+
+                        /* Reset the SERCOM */
+                        usart.ctrla.write(|w| w.swrst().set_bit());
+
+                        /* And wait */
+                        while usart.ctrla.read().swrst().bit_is_set() {}
+                        while usart.status.read().syncbusy().bit_is_set() {}
+
+                        /* Asynchronous mode with internal clock, LSB first */
+                        usart.ctrla.write(|w| {
+                            let w = w.mode().usart_int_clk().dord().set_bit();
+                            let w = w
+                                .rxpo()
+                                .bits(<(TX::Pad, RX::Pad)>::RXPO)
+                                .txpo()
+                                .bit(<(TX::Pad, RX::Pad)>::TXPO);
+                            if config.parity == Parity::None {
+                                w.form()._0()
+                            } else {
+                                w.form()._1()
+                            }
+                        });
+
+                        /* Set frame format and enable receiver and transmitter */
+                        usart.ctrlb.write(|w| unsafe {
+                            w.chsize()
+                                .bits(config.chsize)
+                                .sbmode()
+                                .bit(config.stop_bits == StopBits::Two)
+                                .pmode()
+                                .bit(config.parity == Parity::Odd)
+                                .txen()
+                                .set_bit()
+                                .rxen()
+                                .set_bit()
+                        });
+
+                        /* And wait */
+                        while usart.status.read().syncbusy().bit_is_set() {}
+
+                        usart
+                            .baud
+                            .write(|w| unsafe { w.baud().bits(baud) });
+
+                        /* Enable */
+                        usart.ctrla.modify(|_, w| w.enable().set_bit());
+
+                        /* And wait */
+                        while usart.status.read().syncbusy().bit_is_set() {}
+                    }
+
+                    Ok(Usart {
+                        sercom,
+                        tx,
+                        rx,
+                        pending: false,
+                        _word: PhantomData,
+                    })
+                }
+
+                /* Switch to interrupt-driven mode: received characters are collected in `rx`
+                 * and written characters are queued in `tx` by `handle_interrupt_sercomN`, one slot of
+                 * each stays unused */
+                pub fn listen(&mut self, rx: &'static mut [u16], tx: &'static mut [u16]) {
+                    let usart = self.sercom.usart();
+
+                    interrupt::free(move |cs| {
+                        let nvic = NVIC.borrow(cs);
+
+                        unsafe {
+                            RX_BUFFERS[$n].init(rx);
+                            TX_BUFFERS[$n].init(tx);
+                            RX_ERRORS[$n] = 0;
+                        }
+
+                        /* Receive interrupts are always on, DRE only while there's data to send */
+                        usart.intenset.write(|w| w.rxc().set_bit());
+
+                        /* Enable SERCOM IRQs */
+                        nvic.enable(Interrupt::$SERCOM);
+                    });
+                }
+
+                fn read_word(&mut self) -> nb::Result<u16, Error> {
+                    let usart = self.sercom.usart();
+
+                    if unsafe { RX_BUFFERS[$n].is_initialised() } {
+                        let errors = interrupt::free(|_| unsafe {
+                            let errors = RX_ERRORS[$n];
+                            RX_ERRORS[$n] = 0;
+                            errors
+                        });
+
+                        if let Some(error) = error_from_status(errors) {
+                            return Err(nb::Error::Other(error));
+                        }
+
+                        return unsafe { RX_BUFFERS[$n].pop() }.ok_or(nb::Error::WouldBlock);
+                    }
+
+                    let status = usart.status.read().bits();
+                    if let Some(error) = error_from_status(status) {
+                        /* Clear the errors, they're write-one-to-clear */
+                        usart.status.write(|w| unsafe { w.bits(status) });
+                        return Err(nb::Error::Other(error));
+                    }
+
+                    if usart.intflag.read().rxc().bit_is_set() {
+                        Ok(usart.data.read().data().bits())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn write_word(&mut self, word: u16) -> nb::Result<(), Error> {
+                    let usart = self.sercom.usart();
+
+                    if unsafe { TX_BUFFERS[$n].is_initialised() } {
+                        unsafe { TX_BUFFERS[$n].push(word) }.map_err(|_| nb::Error::WouldBlock)?;
+
+                        /* Have the handler pick it up */
+                        usart.intenset.write(|w| w.dre().set_bit());
+                        self.pending = true;
+                        return Ok(());
+                    }
+
+                    if usart.intflag.read().dre().bit_is_set() {
+                        usart.data.write(|w| unsafe { w.data().bits(word) });
+                        self.pending = true;
+                        Ok(())
+                    } else {
+                        Err(nb::Error::WouldBlock)
+                    }
+                }
+
+                fn flush_words(&mut self) -> nb::Result<(), Error> {
+                    let usart = self.sercom.usart();
+
+                    if !self.pending {
+                        return Ok(());
+                    }
+
+                    /* TXC is only set once the last character left the shift register and
+                     * nothing else is waiting */
+                    if unsafe { !TX_BUFFERS[$n].is_empty() } || usart.intflag.read().txc().bit_is_clear() {
+                        return Err(nb::Error::WouldBlock);
+                    }
+
+                    self.pending = false;
+                    Ok(())
+                }
+
+                /* Keep the USART running in standby, which needs its generic clock to run in
+                 * standby as well. The SERCOM is briefly disabled, cutting short a transfer in
+                 * progress */
+                pub fn set_run_in_standby(&mut self, run_in_standby: bool) {
+                    power::sercom_run_in_standby(&self.sercom, run_in_standby);
+                }
+
+                /* Hand back SERCOM and pins after disabling the USART */
+                pub fn free(self, pm: &PM) -> ($SERCOM, TX, RX) {
+                    let usart = self.sercom.usart();
+
+                    interrupt::free(|_| unsafe {
+                        usart.intenclr.write(|w| w.bits(0xFF));
+                        RX_BUFFERS[$n] = RingBuffer::empty();
+                        TX_BUFFERS[$n] = RingBuffer::empty();
+                    });
+
+                    usart.ctrla.modify(|_, w| w.enable().clear_bit());
+
+                    /* And wait */
+                    while usart.status.read().syncbusy().bit_is_set() {}
+
+                    pm.apbcmask.modify(|_, w| w.$sercom().clear_bit());
+
+                    (self.sercom, self.tx, self.rx)
+                }
+            }
+
+            /* To be called from the interrupt handler of the SERCOM */
+            pub fn $handle_interrupt() {
+                let usart = unsafe { (*$SERCOM::ptr()).usart() };
+                let flags = usart.intflag.read();
+
+                if flags.rxc().bit_is_set() {
+                    let status = usart.status.read().bits();
+                    let data = usart.data.read().data().bits();
+
+                    if status & (STATUS_PERR | STATUS_FERR | STATUS_BUFOVF) != 0 {
+                        /* Clear the errors and remember them for the next read */
+                        usart.status.write(|w| unsafe { w.bits(status) });
+                        unsafe { RX_ERRORS[$n] |= status };
+                    } else if unsafe { RX_BUFFERS[$n].push(data) }.is_err() {
+                        unsafe { RX_ERRORS[$n] |= STATUS_BUFOVF };
+                    }
+                }
+
+                if flags.dre().bit_is_set() && usart.intenset.read().dre().bit_is_set() {
+                    match unsafe { TX_BUFFERS[$n].pop() } {
+                        Some(data) => usart.data.write(|w| unsafe { w.data().bits(data) }),
+                        None => usart.intenclr.write(|w| w.dre().set_bit()),
+                    }
+                }
+            }
+
+            impl<TX, RX, WORD> WakeSource for Usart<$SERCOM, TX, RX, WORD> {
+                fn is_armed(&self, pm: &PM) -> bool {
+                    power::bus_clock_enabled(pm, &self.sercom)
+                        && power::nvic_enabled(Interrupt::$SERCOM)
+                        && self.sercom.usart().intenset.read().bits() != 0
+                }
+            }
+
+            impl<TX, RX> Read<u8> for Usart<$SERCOM, TX, RX, u8>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u8, Error> {
+                    self.read_word().map(|word| word as u8)
+                }
+            }
+
+            impl<TX, RX> Read<u16> for Usart<$SERCOM, TX, RX, u16>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+                type Error = Error;
+
+                fn read(&mut self) -> nb::Result<u16, Error> {
+                    self.read_word()
+                }
+            }
+
+            impl<TX, RX> Write<u8> for Usart<$SERCOM, TX, RX, u8>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+                type Error = Error;
+
+                fn write(&mut self, word: u8) -> nb::Result<(), Error> {
+                    self.write_word(word as u16)
+                }
+
+                fn flush(&mut self) -> nb::Result<(), Error> {
+                    self.flush_words()
+                }
+            }
+
+            impl<TX, RX> Write<u16> for Usart<$SERCOM, TX, RX, u16>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+                type Error = Error;
+
+                fn write(&mut self, word: u16) -> nb::Result<(), Error> {
+                    self.write_word(word)
+                }
+
+                fn flush(&mut self) -> nb::Result<(), Error> {
+                    self.flush_words()
+                }
+            }
+
+            impl<TX, RX> blocking::serial::write::Default<u8> for Usart<$SERCOM, TX, RX, u8>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+            }
+
+            impl<TX, RX> blocking::serial::write::Default<u16> for Usart<$SERCOM, TX, RX, u16>
+            where
+                TX: SercomPad<$SERCOM>,
+                RX: SercomPad<$SERCOM>,
+                (TX::Pad, RX::Pad): UsartPads,
+            {
+            }
+        )+
+    };
+}
+
+usart!(
+    SERCOM0: (sercom0, 0, Sercom0Core, handle_interrupt_sercom0),
+    SERCOM1: (sercom1, 1, Sercom1Core, handle_interrupt_sercom1),
+    SERCOM2: (sercom2, 2, Sercom2Core, handle_interrupt_sercom2),
+    SERCOM3: (sercom3, 3, Sercom3Core, handle_interrupt_sercom3),
+);
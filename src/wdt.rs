@@ -0,0 +1,166 @@
+use super::clock::{self, ClockId, Clocks};
+use super::power::{self, WakeSource};
+use super::system::{self, ResetCause};
+use super::time::{Hertz, MilliSeconds};
+use super::{Interrupt, NVIC, PM, WDT};
+
+extern crate cortex_m;
+
+use cortex_m::interrupt;
+use embedded_hal::watchdog::{Watchdog, WatchdogDisable, WatchdogEnable};
+
+/* Early warning callback, run by `handle_interrupt` */
+static mut EARLY_WARNING: Option<fn()> = None;
+
+/* Whether the last reset was caused by the watchdog */
+pub fn reset_by_watchdog(pm: &PM) -> bool {
+    system::reset_cause(pm) == Some(ResetCause::Watchdog)
+}
+
+/* Watchdog on the generic clock routed to ClockId::Wdt, usually a slow one such as OSCULP32K.
+ * The clock is selected like for every other peripheral, with `ClockConfig::route(ClockId::Wdt,
+ * generator)` before freezing the clock tree. Periods are given in milliseconds and rounded up to
+ * the next 8 * 2^n clock cycles */
+pub struct Wdt {
+    wdt: WDT,
+    clock: Hertz,
+}
+
+impl Wdt {
+    pub fn new(wdt: WDT, clocks: &Clocks) -> Result<Wdt, clock::Error> {
+        let clock = clocks.require(ClockId::Wdt)?;
+
+        Ok(Wdt { wdt, clock })
+    }
+
+    /* Setting of PER, WINDOW and EWOFFSET for `time`, from 8 to 16384 cycles */
+    fn cycles_setting(&self, time: MilliSeconds) -> u8 {
+        let cycles = (time.0 as u64 * self.clock.0 as u64 + 999) / 1000;
+
+        (0..11).find(|n| 8u64 << n >= cycles).unwrap_or(11)
+    }
+
+    /* Configuration may only change while the watchdog is disabled */
+    fn disabled<F: FnOnce(&WDT)>(&mut self, f: F) {
+        let enabled = self.wdt.ctrl.read().enable().bit_is_set();
+
+        if enabled {
+            self.disable();
+        }
+
+        f(&self.wdt);
+
+        /* And wait */
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+
+        if enabled {
+            self.wdt.ctrl.modify(|_, w| w.enable().set_bit());
+
+            /* And wait */
+            while self.wdt.status.read().syncbusy().bit_is_set() {}
+        }
+    }
+
+    /* In window mode feeding is only allowed once `closed` has passed after the previous feed,
+     * earlier feeds reset the chip. The period then counts from the end of the closed window */
+    pub fn set_window(&mut self, closed: Option<MilliSeconds>) {
+        let window = closed.map(|time| self.cycles_setting(time));
+
+        self.disabled(|wdt| match window {
+            Some(window) => {
+                wdt.config.modify(|_, w| unsafe { w.window().bits(window) });
+                wdt.ctrl.modify(|_, w| w.wen().set_bit());
+            }
+            None => wdt.ctrl.modify(|_, w| w.wen().clear_bit()),
+        });
+    }
+
+    /* Call `callback` from `handle_interrupt` once `offset` has passed without a feed, as a last
+     * chance before the reset */
+    pub fn set_early_warning(&mut self, offset: MilliSeconds, callback: fn()) {
+        let offset = self.cycles_setting(offset);
+
+        self.wdt.intenclr.write(|w| w.ew().set_bit());
+        unsafe { EARLY_WARNING = Some(callback) };
+
+        self.disabled(|wdt| wdt.ewctrl.write(|w| unsafe { w.ewoffset().bits(offset) }));
+
+        /* Clear any stale interrupt and enable it */
+        self.wdt.intflag.write(|w| w.ew().set_bit());
+        self.wdt.intenset.write(|w| w.ew().set_bit());
+
+        interrupt::free(|cs| {
+            let nvic = NVIC.borrow(cs);
+
+            /* Enable WDT IRQs */
+            nvic.enable(Interrupt::WDT);
+        });
+    }
+
+    /* To be called from the interrupt handler of the WDT */
+    pub fn handle_interrupt() {
+        let wdt = unsafe { &*WDT::ptr() };
+
+        if wdt.intflag.read().ew().bit_is_set() {
+            wdt.intflag.write(|w| w.ew().set_bit());
+
+            if let Some(callback) = unsafe { EARLY_WARNING } {
+                callback();
+            }
+        }
+    }
+
+    /* Hand back the WDT after disabling it */
+    pub fn free(mut self) -> WDT {
+        self.disable();
+        self.wdt.intenclr.write(|w| w.ew().set_bit());
+        unsafe { EARLY_WARNING = None };
+
+        self.wdt
+    }
+}
+
+/* Only the early warning wakes us up, a watchdog reset always does anyway */
+impl WakeSource for Wdt {
+    fn is_armed(&self, pm: &PM) -> bool {
+        power::bus_clock_enabled(pm, &self.wdt)
+            && power::nvic_enabled(Interrupt::WDT)
+            && self.wdt.intenset.read().ew().bit_is_set()
+    }
+}
+
+impl WatchdogEnable for Wdt {
+    type Time = MilliSeconds;
+
+    fn start<T: Into<MilliSeconds>>(&mut self, period: T) {
+        let period = self.cycles_setting(period.into());
+
+        self.disabled(|wdt| wdt.config.modify(|_, w| unsafe { w.per().bits(period) }));
+
+        /* Enable */
+        self.wdt.ctrl.modify(|_, w| w.enable().set_bit());
+
+        /* And wait */
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+    }
+}
+
+impl Watchdog for Wdt {
+    /* Write the CLEAR key, after any previous one has been synchronised so the bus isn't stalled
+     * for several watchdog clock cycles */
+    fn feed(&mut self) {
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+
+        self.wdt.clear.write(|w| w.clear().key());
+    }
+}
+
+/* Has no effect if the watchdog has been enabled with ALWAYSON or by the fuses */
+impl WatchdogDisable for Wdt {
+    fn disable(&mut self) {
+        self.wdt.ctrl.modify(|_, w| w.enable().clear_bit());
+
+        /* And wait */
+        while self.wdt.status.read().syncbusy().bit_is_set() {}
+    }
+}